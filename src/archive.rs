@@ -0,0 +1,29 @@
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+
+use zip::ZipArchive;
+
+use crate::util::read_error;
+use crate::Error;
+
+pub(crate) fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            ["zip", "pk3", "pk7", "pke"].contains(&ext.to_string_lossy().to_lowercase().as_str())
+        })
+        .unwrap_or(false)
+}
+
+/// Lists the paths of the files inside the zip archive (or pk3, etc.) at
+/// `path`.
+pub(crate) fn archive_entries(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let archive =
+        ZipArchive::new(File::open(path).map_err(read_error(path))?).map_err(|error| {
+            Error::ReadingZip {
+                path: path.to_path_buf(),
+                error,
+            }
+        })?;
+    Ok(archive.file_names().map(PathBuf::from).collect())
+}
@@ -5,6 +5,7 @@ pub(crate) struct CommandLine {
 
 #[derive(Debug, Clone)]
 pub(crate) struct Line {
+    indentation_depth: usize,
     words: Vec<String>,
 }
 
@@ -17,52 +18,95 @@ impl CommandLine {
         self.lines.push(line);
     }
 
+    /// Adds a line setting the ZDoom console variable `name` to `value`.
+    pub fn push_cvar(&mut self, name: &str, value: &str) {
+        self.push_line(Line::from_cvar(name, value, 1));
+    }
+
+    /// Adds `args` as they are, one per line. They should come after
+    /// everything else so that they can override it.
+    pub fn push_passthrough(&mut self, args: &[&str]) {
+        for arg in args {
+            self.push_line(Line::from_word(arg, 1));
+        }
+    }
+
     pub fn iter_words(&self) -> impl Iterator<Item = &str> {
-        self.lines.iter().map(|line| line.iter()).flatten()
+        self.lines.iter().flat_map(|line| line.iter())
     }
 
     pub fn iter_lines(&self) -> impl Iterator<Item = &Line> {
         self.lines.iter()
     }
+
+    /// Formats the command line for display, wrapping any line longer than
+    /// `max_width` onto indented continuation lines.
+    pub fn display(&self, max_width: usize) -> String {
+        self.iter_lines()
+            .flat_map(|line| line.wrap(max_width))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
 const INDENTATION_WIDTH: usize = 4;
 
 impl Line {
-    fn indent_word(word: impl AsRef<str>, indentation_depth: usize) -> String {
-        let mut indented_word =
-            String::with_capacity(INDENTATION_WIDTH * indentation_depth + word.as_ref().len());
-        for _ in 0..(indentation_depth * INDENTATION_WIDTH) {
-            indented_word.push(' ');
-        }
-        indented_word.push_str(word.as_ref());
-        indented_word
+    fn indentation(indentation_depth: usize) -> String {
+        " ".repeat(indentation_depth * INDENTATION_WIDTH)
     }
 
     pub fn from_words(words: &[impl AsRef<str>], indentation_depth: usize) -> Self {
         Self {
-            words: words
-                .iter()
-                .next()
-                .map(|first| Self::indent_word(first, indentation_depth))
-                .into_iter()
-                .chain(words.iter().skip(1).map(|w| w.as_ref().to_string()))
-                .collect(),
+            indentation_depth,
+            words: words.iter().map(|w| w.as_ref().to_string()).collect(),
         }
     }
 
     pub fn from_word(word: impl AsRef<str>, indentation_depth: usize) -> Self {
         Self {
-            words: vec![Self::indent_word(word, indentation_depth)],
+            indentation_depth,
+            words: vec![word.as_ref().to_string()],
         }
     }
 
-    pub fn iter(&self) -> LineIterator {
+    /// A line setting a ZDoom console variable, as `+name value`.
+    pub fn from_cvar(name: &str, value: &str, indentation_depth: usize) -> Self {
+        Self {
+            indentation_depth,
+            words: vec![format!("+{}", name), value.to_owned()],
+        }
+    }
+
+    pub fn iter(&self) -> LineIterator<'_> {
         LineIterator {
             line: self,
             index: 0,
         }
     }
+
+    /// Splits the line into display lines of at most `max_width` characters
+    /// where possible. Continuation lines are indented one level deeper than
+    /// the first; a single word is never broken.
+    pub fn wrap(&self, max_width: usize) -> Vec<String> {
+        let mut display_lines = vec![];
+        let mut current = Self::indentation(self.indentation_depth);
+        let mut current_has_words = false;
+        for word in self.iter() {
+            if current_has_words && current.len() + 1 + word.len() > max_width {
+                display_lines.push(current);
+                current = Self::indentation(self.indentation_depth + 1);
+                current_has_words = false;
+            }
+            if current_has_words {
+                current.push(' ');
+            }
+            current.push_str(word);
+            current_has_words = true;
+        }
+        display_lines.push(current);
+        display_lines
+    }
 }
 
 pub(crate) struct LineIterator<'l> {
@@ -94,3 +138,79 @@ impl<'l> Iterator for LineIterator<'l> {
         value.map(String::as_str)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passthrough_comes_last() {
+        let mut cmdline = CommandLine::new();
+        cmdline.push_line(Line::from_word("prboom-plus", 0));
+        cmdline.push_line(Line::from_words(&["-iwad", "doom2.wad"], 1));
+        cmdline.push_cvar("vid_fps", "1");
+        cmdline.push_passthrough(&["-nosound", "+map", "map01"]);
+        assert_eq!(
+            cmdline.iter_words().collect::<Vec<_>>(),
+            [
+                "prboom-plus",
+                "-iwad",
+                "doom2.wad",
+                "+vid_fps",
+                "1",
+                "-nosound",
+                "+map",
+                "map01",
+            ]
+        );
+    }
+
+    #[test]
+    fn from_words_indents_the_line() {
+        let line = Line::from_words(&["-file", "a.wad"], 2);
+        assert_eq!(line.wrap(80), ["        -file a.wad"]);
+    }
+
+    #[test]
+    fn wraps_long_lines() {
+        let line = Line::from_words(&["-file", "first.wad", "second.wad", "third.wad"], 1);
+        assert_eq!(
+            line.wrap(28),
+            ["    -file first.wad", "        second.wad third.wad",]
+        );
+    }
+
+    #[test]
+    fn never_breaks_a_word() {
+        let line = Line::from_words(&["-file", "a-very-long-wad-name.wad"], 1);
+        assert_eq!(
+            line.wrap(10),
+            ["    -file", "        a-very-long-wad-name.wad"]
+        );
+    }
+
+    #[test]
+    fn wrapping_is_only_for_display() {
+        let mut cmdline = CommandLine::new();
+        cmdline.push_line(Line::from_word("prboom-plus", 0));
+        cmdline.push_line(Line::from_words(&["-file", "first.wad", "second.wad"], 1));
+        assert_eq!(
+            cmdline.display(20),
+            "prboom-plus\n    -file first.wad\n        second.wad"
+        );
+        assert_eq!(
+            cmdline.iter_words().collect::<Vec<_>>(),
+            ["prboom-plus", "-file", "first.wad", "second.wad"]
+        );
+    }
+
+    #[test]
+    fn cvars_are_two_words() {
+        let line = Line::from_cvar("sv_motd", "Welcome to hell", 1);
+        assert_eq!(
+            line.iter().collect::<Vec<_>>(),
+            ["+sv_motd", "Welcome to hell"]
+        );
+        assert_eq!(line.wrap(80), ["    +sv_motd Welcome to hell"]);
+    }
+}
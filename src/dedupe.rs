@@ -0,0 +1,119 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+
+use log::trace;
+
+use crate::search::SEARCH_INDEX;
+use crate::util::read_error;
+use crate::Error;
+
+/// The extensions of the files compared for duplicates.
+const WAD_EXTENSIONS: [&str; 7] = ["wad", "pk3", "pk7", "pke", "zip", "deh", "bex"];
+
+/// Files with identical contents.
+pub(crate) struct DuplicateGroup {
+    pub size: u64,
+    pub paths: Vec<PathBuf>,
+}
+
+/// Finds every set of identical WADs under `dirs`.
+///
+/// Files are only hashed when another file has the same size, so most of them
+/// are never read.
+pub(crate) fn find_duplicates(dirs: &[PathBuf]) -> Result<Vec<DuplicateGroup>, Error> {
+    let mut seen = HashSet::new();
+    let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+    let mut index = SEARCH_INDEX.lock().unwrap();
+    for dir in dirs.iter().filter(|dir| dir.exists()) {
+        for file in index.files(dir)? {
+            if !is_wad(file) || !seen.insert(file.canonicalize().map_err(read_error(file))?) {
+                continue;
+            }
+            let size = file.metadata().map_err(read_error(file))?.len();
+            by_size.entry(size).or_default().push(file.clone());
+        }
+    }
+    drop(index);
+
+    let mut groups = vec![];
+    for (size, paths) in by_size.into_iter().filter(|(_, paths)| paths.len() > 1) {
+        let mut by_hash: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        for path in paths {
+            trace!("Hashing '{}'", path.to_string_lossy());
+            by_hash.entry(hash_file(&path)?).or_default().push(path);
+        }
+        for (_, mut paths) in by_hash.into_iter().filter(|(_, paths)| paths.len() > 1) {
+            paths.sort();
+            groups.push(DuplicateGroup { size, paths });
+        }
+    }
+    Ok(groups)
+}
+
+fn is_wad(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| WAD_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn hash_file(path: &Path) -> Result<u64, Error> {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&std::fs::read(path).map_err(read_error(path))?);
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_identical_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.wad", "b/a.wad", "c.pk3"] {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "PWAD").unwrap();
+        }
+        std::fs::write(dir.path().join("notes.txt"), "PWAD").unwrap();
+
+        let groups = find_duplicates(&[dir.path().to_owned()]).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].size, 4);
+        assert_eq!(
+            groups[0].paths,
+            [
+                dir.path().join("a.wad"),
+                dir.path().join("b/a.wad"),
+                dir.path().join("c.pk3")
+            ]
+        );
+    }
+
+    #[test]
+    fn same_size_is_not_enough() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.wad"), "PWAD").unwrap();
+        std::fs::write(dir.path().join("b.wad"), "IWAD").unwrap();
+        assert!(find_duplicates(&[dir.path().to_owned()])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_file_counts_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let wad = dir.path().join("a.wad");
+        std::fs::write(&wad, "PWAD").unwrap();
+        std::os::unix::fs::symlink(&wad, dir.path().join("link.wad")).unwrap();
+        assert!(find_duplicates(&[dir.path().to_owned()])
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,588 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::search::SEARCH_INDEX;
+use crate::util::glob_match;
+use crate::util::read_error;
+use crate::Error;
+
+/// Marks the end of the tic data in a demo.
+const DEMO_MARKER: u8 = 0x80;
+/// Doom runs at 35 tics per second.
+pub(crate) const TICRATE: u32 = 35;
+
+/// What we can learn from a demo's header.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DemoHeader {
+    /// The length of the header in bytes, i.e. where the tic data starts.
+    len: usize,
+    players: usize,
+    longtics: bool,
+    episode: u8,
+    map: u8,
+}
+
+impl DemoHeader {
+    pub fn parse(demo: &[u8]) -> Option<Self> {
+        let version = *demo.first()?;
+        // Where the episode is; the map always follows it.
+        let (len, players, episode) = match version {
+            // Doom 1.2 and earlier have no version byte; the first byte is the skill.
+            0..=4 => (7, count_players(demo.get(3..7)?), 1),
+            104..=111 => (13, count_players(demo.get(9..13)?), 2),
+            // Boom, MBF and PrBoom+: version, signature, compatibility, skill,
+            // episode, map, deathmatch, console player, 64 bytes of options and
+            // 32 players.
+            200..=203 | 214 => (109, count_players(demo.get(77..109)?), 9),
+            _ => return None,
+        };
+        Some(Self {
+            len,
+            players,
+            longtics: version == 111 || version == 214,
+            episode: *demo.get(episode)?,
+            map: *demo.get(episode + 1)?,
+        })
+    }
+
+    /// The size of one tic of input, for all players.
+    fn tic_len(&self) -> usize {
+        self.players * if self.longtics { 5 } else { 4 }
+    }
+}
+
+/// The family of engines a demo was recorded with, going by its version byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub(crate) enum DemoFormat {
+    /// Doom 1.2 and earlier.
+    Doom12,
+    /// Doom 1.4 to 1.9, including PrBoom+'s longtics variant.
+    Vanilla,
+    Boom,
+    MBF,
+    /// PrBoom and PrBoom+ at their own compatibility levels.
+    PrBoom,
+}
+
+impl DemoFormat {
+    fn from_version(version: u8) -> Option<Self> {
+        match version {
+            0..=4 => Some(Self::Doom12),
+            104..=111 => Some(Self::Vanilla),
+            200..=202 => Some(Self::Boom),
+            203 => Some(Self::MBF),
+            214 => Some(Self::PrBoom),
+            _ => None,
+        }
+    }
+
+    /// The compatibility levels that record demos in this format.
+    pub fn complevels(self) -> RangeInclusive<u8> {
+        match self {
+            Self::Doom12 => 0..=0,
+            Self::Vanilla => 1..=4,
+            Self::Boom => 5..=7,
+            Self::MBF => 8..=8,
+            Self::PrBoom => 9..=21,
+        }
+    }
+
+    /// The compatibility level demos in this format play back at, if there's
+    /// only the one.
+    pub fn complevel(self) -> Option<u8> {
+        let levels = self.complevels();
+        (levels.start() == levels.end()).then(|| *levels.start())
+    }
+}
+
+impl Display for DemoFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Doom12 => "Doom 1.2",
+            Self::Vanilla => "vanilla",
+            Self::Boom => "Boom",
+            Self::MBF => "MBF",
+            Self::PrBoom => "PrBoom+",
+        })
+    }
+}
+
+/// Works out which format `demo` was recorded in from its header.
+pub(crate) fn demo_format(demo: &Path) -> Result<DemoFormat, Error> {
+    let data = std::fs::read(demo).map_err(read_error(demo))?;
+    data.first()
+        .copied()
+        .and_then(DemoFormat::from_version)
+        .ok_or_else(|| Error::UnknownDemoFormat(demo.to_string_lossy().into_owned()))
+}
+
+fn count_players(playeringame: &[u8]) -> usize {
+    playeringame.iter().filter(|&&p| p != 0).count()
+}
+
+/// Guesses which IWADs `demo` could have been recorded on, most likely first.
+///
+/// The header's episode and map tell Doom and Doom 2 apart where they can, and
+/// a text file next to the demo with the same name, as demos are usually
+/// distributed with, can narrow things down further by mentioning the IWAD.
+pub(crate) fn demo_iwads(demo: &Path) -> Result<Vec<&'static str>, Error> {
+    const DOOM: &[&str] = &["DOOM.WAD", "DOOMU.WAD"];
+    const DOOM2: &[&str] = &["DOOM2.WAD", "TNT.WAD", "PLUTONIA.WAD"];
+
+    let data = std::fs::read(demo).map_err(read_error(demo))?;
+    let mut candidates = match DemoHeader::parse(&data) {
+        Some(header) if header.episode > 1 => DOOM.to_vec(),
+        Some(header) if header.map > 9 => DOOM2.to_vec(),
+        _ => DOOM2.iter().chain(DOOM).copied().collect(),
+    };
+
+    let text = demo.with_extension("txt");
+    if let Ok(text) = std::fs::read_to_string(&text) {
+        let text = text.to_lowercase();
+        let mentioned = |iwad: &&str| match *iwad {
+            "DOOM2.WAD" => text.contains("doom2") || text.contains("doom ii"),
+            "TNT.WAD" => text.contains("tnt") || text.contains("evilution"),
+            "PLUTONIA.WAD" => text.contains("plutonia"),
+            _ => text.contains("ultimate doom") || text.contains("doom.wad"),
+        };
+        if candidates.iter().any(mentioned) {
+            candidates.retain(mentioned);
+        }
+    }
+    Ok(candidates)
+}
+
+/// The IWADs a demo's text file might name, which are told apart from the
+/// PWADs it names.
+const IWAD_NAMES: [&str; 11] = [
+    "doom.wad",
+    "doom1.wad",
+    "doomu.wad",
+    "doom2.wad",
+    "tnt.wad",
+    "plutonia.wad",
+    "freedoom1.wad",
+    "freedoom2.wad",
+    "heretic.wad",
+    "hexen.wad",
+    "strife1.wad",
+];
+
+/// What a demo's text file says about how it was recorded.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DemoMeta {
+    pub iwad: Option<String>,
+    pub pwads: Vec<String>,
+    pub complevel: Option<u8>,
+}
+
+/// Reads the IWAD, PWADs and compatibility level from the text file a demo was
+/// distributed with.
+///
+/// There's no standard layout, so this looks for lines like `Complevel: 9`,
+/// `Wad: foo.wad` or `PWADs = foo.wad, foo.deh`, in any case, and for the
+/// `-iwad`, `-file` and `-complevel` arguments of a command line quoted in the
+/// file. Anything it doesn't understand is ignored.
+pub(crate) fn parse_demo_txt(path: &Path) -> Result<DemoMeta, Error> {
+    let text = std::fs::read(path).map_err(read_error(path))?;
+    let text = String::from_utf8_lossy(&text);
+    let mut meta = DemoMeta::default();
+    for line in text.lines() {
+        if let Some((key, value)) = line.split_once([':', '=']) {
+            let key = key
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_lowercase();
+            match key.as_str() {
+                "iwad" => {
+                    if let Some(iwad) = value.split_whitespace().next() {
+                        meta.iwad.get_or_insert_with(|| unquote(iwad).to_owned());
+                    }
+                }
+                "wad" | "wads" | "pwad" | "pwads" | "file" | "files" | "requires" => {
+                    for word in value.split(|c: char| c == ',' || c == '+' || c.is_whitespace()) {
+                        add_wad(&mut meta, word);
+                    }
+                }
+                "complevel" | "compatibilitylevel" | "compatlevel" | "cl" => {
+                    if let Some(level) = first_number(value) {
+                        meta.complevel.get_or_insert(level);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // A quoted command line, as in `prboom-plus -iwad doom2 -file foo.wad -complevel 9`.
+        let mut words = line.split_whitespace().peekable();
+        while let Some(word) = words.next() {
+            match word.to_lowercase().as_str() {
+                "-iwad" => {
+                    if let Some(iwad) = words.next() {
+                        meta.iwad.get_or_insert_with(|| unquote(iwad).to_owned());
+                    }
+                }
+                "-file" => {
+                    while let Some(wad) = words.next_if(|word| !word.starts_with('-')) {
+                        add_wad(&mut meta, wad);
+                    }
+                }
+                "-complevel" => {
+                    if let Some(level) = words.next().and_then(first_number) {
+                        meta.complevel.get_or_insert(level);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    meta.complevel = meta.complevel.filter(|level| *level <= 21);
+    Ok(meta)
+}
+
+/// Adds `word` to `meta` if it names a WAD or patch: as its IWAD if it's one
+/// of the known IWADs, and otherwise to its PWADs.
+fn add_wad(meta: &mut DemoMeta, word: &str) {
+    let word = unquote(word);
+    let lower = word.to_lowercase();
+    if IWAD_NAMES.contains(&lower.as_str()) {
+        meta.iwad.get_or_insert_with(|| word.to_owned());
+    } else if [".wad", ".deh", ".bex", ".pk3", ".zip"]
+        .iter()
+        .any(|ext| lower.ends_with(ext))
+        && !meta
+            .pwads
+            .iter()
+            .any(|pwad| pwad.eq_ignore_ascii_case(word))
+    {
+        meta.pwads.push(word.to_owned());
+    }
+}
+
+fn unquote(word: &str) -> &str {
+    word.trim_matches(|c: char| c == '"' || c == '\'' || c == '(' || c == ')')
+}
+
+/// The first run of digits in `text`, as in `9 (Doom 2 1.9)`.
+fn first_number(text: &str) -> Option<u8> {
+    text.split(|c: char| !c.is_ascii_digit())
+        .find(|digits| !digits.is_empty())?
+        .parse()
+        .ok()
+}
+
+/// Lists the reasons playing back `demo` with `iwad` and `pwads` (file names)
+/// at compatibility level `complevel` (`None` leaving it to the engine) would
+/// probably desync, going by its header and the text file next to it.
+pub(crate) fn desync_risks(
+    demo: &Path,
+    iwad: &str,
+    pwads: &[String],
+    complevel: Option<u8>,
+) -> Result<Vec<String>, Error> {
+    const RELEASED: &[&str] = &[
+        "DOOM.WAD",
+        "DOOMU.WAD",
+        "DOOM2.WAD",
+        "TNT.WAD",
+        "PLUTONIA.WAD",
+    ];
+    let stem = |name: &str| {
+        Path::new(name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    };
+
+    let mut risks = vec![];
+    let iwad = iwad.to_uppercase();
+    let candidates = demo_iwads(demo)?;
+    // Other IWADs, like Freedoom's, are never among the candidates.
+    if RELEASED.contains(&iwad.as_str()) && !candidates.contains(&iwad.as_str()) {
+        risks.push(format!(
+            "it was recorded on {}, not {}",
+            candidates.join(" or "),
+            iwad
+        ));
+    }
+
+    let text = demo.with_extension("txt");
+    let meta = if text.exists() {
+        parse_demo_txt(&text)?
+    } else {
+        DemoMeta::default()
+    };
+    if let Some(expected) = meta.iwad.filter(|expected| stem(expected) != stem(&iwad)) {
+        if risks.is_empty() {
+            risks.push(format!(
+                "its text file says it was recorded on {}, not {}",
+                expected, iwad
+            ));
+        }
+    }
+    for pwad in &meta.pwads {
+        if !pwads.iter().any(|loaded| stem(loaded) == stem(pwad)) {
+            risks.push(format!("it needs {}, which isn't loaded", pwad));
+        }
+    }
+
+    if let Some(level) = complevel {
+        match (meta.complevel, demo_format(demo)) {
+            (Some(expected), _) if expected != level => risks.push(format!(
+                "its text file says complevel {}, not {}",
+                expected, level
+            )),
+            (None, Ok(format)) if !format.complevels().contains(&level) => risks.push(format!(
+                "it's a {} demo, which complevel {} doesn't play back",
+                format, level
+            )),
+            _ => {}
+        }
+    }
+    Ok(risks)
+}
+
+/// Counts the tics recorded in `demo`, if its format is understood.
+pub(crate) fn count_tics(demo: &Path) -> Result<Option<u32>, Error> {
+    let data = std::fs::read(demo).map_err(read_error(demo))?;
+    let header = match DemoHeader::parse(&data) {
+        Some(header) if header.players > 0 => header,
+        _ => return Ok(None),
+    };
+    let mut tics = 0;
+    let mut offset = header.len;
+    while offset + header.tic_len() <= data.len() && data[offset] != DEMO_MARKER {
+        offset += header.tic_len();
+        tics += 1;
+    }
+    Ok(Some(tics))
+}
+
+/// Every demo under `dir`, in order of path, keeping only those whose file
+/// names match the glob `only` if given.
+pub(crate) fn demos_in_dir(dir: &Path, only: Option<&str>) -> Result<Vec<PathBuf>, Error> {
+    if !dir.is_dir() {
+        return Err(Error::NotADirectory(dir.to_string_lossy().into_owned()));
+    }
+    let mut demos = SEARCH_INDEX
+        .lock()
+        .unwrap()
+        .files(dir)?
+        .iter()
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("lmp"))
+        })
+        .filter(|path| {
+            only.is_none_or(|only| {
+                glob_match(
+                    only,
+                    &path.file_name().unwrap_or_default().to_string_lossy(),
+                )
+            })
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    demos.sort();
+    Ok(demos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Doom 1.9 demo header for one player on `episode` and `map`.
+    fn vanilla_demo(episode: u8, map: u8) -> Vec<u8> {
+        vec![109, 3, episode, map, 0, 0, 0, 0, 0, 1, 0, 0, 0]
+    }
+
+    /// A Boom-style demo header with version byte `version`, for one player.
+    fn boom_demo(version: u8) -> Vec<u8> {
+        let mut demo = vec![0; 109];
+        demo[0] = version;
+        demo[9] = 1;
+        demo[10] = 1;
+        demo[77] = 1;
+        demo
+    }
+
+    fn write_demo(dir: &Path, name: &str, data: &[u8]) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn classifies_demo_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let format = |data: &[u8]| demo_format(&write_demo(dir.path(), "demo.lmp", data));
+        assert_eq!(
+            format(&[2, 1, 1, 0, 1, 0, 0, 0]).unwrap(),
+            DemoFormat::Doom12
+        );
+        assert_eq!(format(&vanilla_demo(1, 1)).unwrap(), DemoFormat::Vanilla);
+        assert_eq!(format(&boom_demo(202)).unwrap(), DemoFormat::Boom);
+        assert_eq!(format(&boom_demo(203)).unwrap(), DemoFormat::MBF);
+        assert_eq!(format(&boom_demo(214)).unwrap(), DemoFormat::PrBoom);
+        assert!(matches!(
+            format(&[150, 0, 0]),
+            Err(Error::UnknownDemoFormat(_))
+        ));
+    }
+
+    #[test]
+    fn formats_match_their_complevels() {
+        assert!(DemoFormat::Vanilla.complevels().contains(&2));
+        assert!(!DemoFormat::Vanilla.complevels().contains(&9));
+        assert!(DemoFormat::MBF.complevels().contains(&8));
+        assert!(DemoFormat::PrBoom.complevels().contains(&21));
+    }
+
+    #[test]
+    fn some_formats_settle_the_complevel() {
+        assert_eq!(DemoFormat::Doom12.complevel(), Some(0));
+        assert_eq!(DemoFormat::MBF.complevel(), Some(8));
+        assert_eq!(DemoFormat::Vanilla.complevel(), None);
+        assert_eq!(DemoFormat::Boom.complevel(), None);
+        assert_eq!(DemoFormat::PrBoom.complevel(), None);
+    }
+
+    #[test]
+    fn guesses_iwads_from_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let doom = write_demo(dir.path(), "e2m1.lmp", &vanilla_demo(2, 1));
+        assert_eq!(demo_iwads(&doom).unwrap(), ["DOOM.WAD", "DOOMU.WAD"]);
+        let doom2 = write_demo(dir.path(), "map15.lmp", &vanilla_demo(1, 15));
+        assert_eq!(
+            demo_iwads(&doom2).unwrap(),
+            ["DOOM2.WAD", "TNT.WAD", "PLUTONIA.WAD"]
+        );
+        let either = write_demo(dir.path(), "map01.lmp", &vanilla_demo(1, 1));
+        assert_eq!(demo_iwads(&either).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn narrows_iwads_by_text_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let demo = write_demo(dir.path(), "pl01.lmp", &vanilla_demo(1, 1));
+        std::fs::write(dir.path().join("pl01.txt"), "Recorded on Plutonia").unwrap();
+        assert_eq!(demo_iwads(&demo).unwrap(), ["PLUTONIA.WAD"]);
+    }
+
+    #[test]
+    fn finds_demos_in_dir_matching_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("episode2")).unwrap();
+        for name in [
+            "map01-uv.lmp",
+            "map01-nomo.lmp",
+            "episode2/e2m1-NOMO.LMP",
+            "map01-nomo.txt",
+        ] {
+            write_demo(dir.path(), name, &vanilla_demo(1, 1));
+        }
+
+        let all = demos_in_dir(dir.path(), None).unwrap();
+        assert_eq!(all.len(), 3);
+        let nomo = demos_in_dir(dir.path(), Some("*nomo*")).unwrap();
+        assert_eq!(
+            nomo,
+            [
+                dir.path().join("episode2/e2m1-NOMO.LMP"),
+                dir.path().join("map01-nomo.lmp"),
+            ]
+        );
+    }
+
+    #[test]
+    fn demos_in_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let demo = write_demo(dir.path(), "map01.lmp", &vanilla_demo(1, 1));
+        assert!(matches!(
+            demos_in_dir(&demo, None),
+            Err(Error::NotADirectory(_))
+        ));
+    }
+
+    fn demo_txt(text: &str) -> DemoMeta {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("demo.txt");
+        std::fs::write(&path, text).unwrap();
+        parse_demo_txt(&path).unwrap()
+    }
+
+    #[test]
+    fn parses_demo_txt_fields() {
+        let meta = demo_txt(
+            "Map: MAP01\nIWAD: DOOM2.WAD\nPWADs = scythe.wad, scythe.deh\nComplevel: 2 (Doom 2 1.9)\n",
+        );
+        assert_eq!(meta.iwad.as_deref(), Some("DOOM2.WAD"));
+        assert_eq!(meta.pwads, ["scythe.wad", "scythe.deh"]);
+        assert_eq!(meta.complevel, Some(2));
+    }
+
+    #[test]
+    fn parses_demo_txt_command_line() {
+        let meta = demo_txt(
+            "Recorded with:\n  prboom-plus -iwad plutonia.wad -file \"av.wad\" -complevel 4 -record pl01\n",
+        );
+        assert_eq!(meta.iwad.as_deref(), Some("plutonia.wad"));
+        assert_eq!(meta.pwads, ["av.wad"]);
+        assert_eq!(meta.complevel, Some(4));
+    }
+
+    #[test]
+    fn demo_txt_is_parsed_leniently() {
+        let meta = demo_txt("wad:doom.wad\ncl=99\nfiles: readme\nnonsense\n");
+        assert_eq!(meta.iwad.as_deref(), Some("doom.wad"));
+        assert!(meta.pwads.is_empty());
+        assert_eq!(meta.complevel, None);
+    }
+
+    #[test]
+    fn no_desync_risks_when_everything_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let demo = write_demo(dir.path(), "sc01.lmp", &vanilla_demo(1, 1));
+        std::fs::write(
+            dir.path().join("sc01.txt"),
+            "IWAD: doom2.wad\nPWAD: scythe.wad\nComplevel: 2\n",
+        )
+        .unwrap();
+        let risks = desync_risks(&demo, "DOOM2.WAD", &["Scythe.wad".to_owned()], Some(2));
+        assert!(risks.unwrap().is_empty());
+    }
+
+    #[test]
+    fn finds_desync_risks() {
+        let dir = tempfile::tempdir().unwrap();
+        let demo = write_demo(dir.path(), "sc01.lmp", &vanilla_demo(1, 15));
+        std::fs::write(
+            dir.path().join("sc01.txt"),
+            "PWAD: scythe.wad\nComplevel: 2\n",
+        )
+        .unwrap();
+        let risks = desync_risks(&demo, "DOOM.WAD", &[], Some(9)).unwrap();
+        assert_eq!(risks.len(), 3);
+        assert!(risks[0].contains("DOOM2.WAD"));
+        assert!(risks[1].contains("scythe.wad"));
+        assert!(risks[2].contains("complevel 2"));
+    }
+
+    #[test]
+    fn finds_complevel_risk_from_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let demo = write_demo(dir.path(), "map01.lmp", &boom_demo(203));
+        let risks = desync_risks(&demo, "DOOM2.WAD", &[], Some(2)).unwrap();
+        assert_eq!(risks.len(), 1);
+        assert!(risks[0].contains("MBF"));
+        assert!(desync_risks(&demo, "DOOM2.WAD", &[], Some(8))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs::create_dir_all;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Select;
+use log::debug;
+use log::info;
+use log::warn;
+use zip::ZipArchive;
+
+use crate::util::read_error;
+use crate::util::write_error;
+use crate::Error;
+use crate::FIRST_MATCH;
+
+/// A demo extracted from a zip, along with its text file if it had one. The
+/// extracted files are removed when this is dropped.
+pub(crate) struct ExtractedDemo {
+    dir: PathBuf,
+    pub demo: PathBuf,
+}
+
+impl Drop for ExtractedDemo {
+    fn drop(&mut self) {
+        debug!("Removing '{}'", self.dir.to_string_lossy());
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            warn!("Couldn't remove '{}': {}", self.dir.to_string_lossy(), e);
+        }
+    }
+}
+
+/// Whether `path` is a zip that demos are commonly distributed in.
+pub(crate) fn is_demo_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Extracts the demo in the zip at `path` to a temporary directory, asking
+/// which one if there are several. The text file with the same name as the
+/// demo, or the only text file, is extracted next to it so that hints about
+/// how the demo was recorded can be read from it.
+pub(crate) fn extract_demo(path: &Path) -> Result<ExtractedDemo, Error> {
+    let zip_error = |error| Error::ReadingZip {
+        path: path.to_path_buf(),
+        error,
+    };
+    let mut archive =
+        ZipArchive::new(File::open(path).map_err(read_error(path))?).map_err(zip_error)?;
+    let names = archive
+        .file_names()
+        .map(|name| name.to_owned())
+        .collect::<Vec<_>>();
+    let with_extension = |extension: &str| {
+        let mut found = names
+            .iter()
+            .filter(|name| {
+                Path::new(name)
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        found.sort();
+        found
+    };
+
+    let demos = with_extension("lmp");
+    let demo = match demos.len() {
+        0 => return Err(Error::NoDemoInZip(path.to_string_lossy().into_owned())),
+        1 => &demos[0],
+        _ if FIRST_MATCH.load(Ordering::SeqCst) => &demos[0],
+        _ => {
+            &demos[Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!(
+                    "Which demo in {} do you want?",
+                    path.to_string_lossy()
+                ))
+                .items(&demos)
+                .default(0)
+                .interact()
+                .map_err(Error::Io)?]
+        }
+    };
+    let demo_stem = Path::new(demo).file_stem().unwrap_or_default();
+    let texts = with_extension("txt");
+    let text = texts
+        .iter()
+        .find(|text| Path::new(text).file_stem() == Some(demo_stem))
+        .or(match texts.as_slice() {
+            [only] => Some(only),
+            _ => None,
+        });
+
+    let dir = std::env::temp_dir().join(format!(
+        "playdoom-{}-{}",
+        std::process::id(),
+        path.file_stem().unwrap_or_default().to_string_lossy()
+    ));
+    create_dir_all(&dir).map_err(write_error(&dir))?;
+    let extracted = ExtractedDemo {
+        demo: dir.join(Path::new(demo).file_name().unwrap_or_default()),
+        dir,
+    };
+    info!("Extracting '{}' from '{}'", demo, path.to_string_lossy());
+    extract_file(&mut archive, demo, &extracted.demo).map_err(zip_error)?;
+    if let Some(text) = text {
+        extract_file(&mut archive, text, &extracted.demo.with_extension("txt"))
+            .map_err(zip_error)?;
+    }
+    Ok(extracted)
+}
+
+/// Copies the file `name` out of `archive` to `to`.
+fn extract_file(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+    to: &Path,
+) -> Result<(), zip::result::ZipError> {
+    let mut file = archive.by_name(name)?;
+    let mut out = File::create(to)?;
+    std::io::copy(&mut file, &mut out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn recognizes_zips() {
+        assert!(is_demo_zip(Path::new("e1m1-uv.zip")));
+        assert!(is_demo_zip(Path::new("E1M1-UV.ZIP")));
+        assert!(!is_demo_zip(Path::new("e1m1-uv.lmp")));
+        assert!(!is_demo_zip(Path::new("zip")));
+    }
+
+    #[test]
+    fn extracts_demo_and_text_then_cleans_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip = dir.path().join("playdoom-test-extract.zip");
+        write_zip(
+            &zip,
+            &[
+                ("readme.txt", b"iwad: doom2"),
+                ("lv01-039.lmp", b"demo"),
+                ("lv01-039.txt", b"iwad: doom2.wad"),
+            ],
+        );
+
+        let extracted = extract_demo(&zip).unwrap();
+        let extracted_dir = extracted.dir.clone();
+        assert_eq!(
+            extracted.demo.file_name().unwrap().to_string_lossy(),
+            "lv01-039.lmp"
+        );
+        assert_eq!(std::fs::read(&extracted.demo).unwrap(), b"demo");
+        assert_eq!(
+            std::fs::read(extracted.demo.with_extension("txt")).unwrap(),
+            b"iwad: doom2.wad"
+        );
+
+        drop(extracted);
+        assert!(!extracted_dir.exists());
+    }
+
+    #[test]
+    fn zip_without_demo_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip = dir.path().join("playdoom-test-no-demo.zip");
+        write_zip(&zip, &[("readme.txt", b"nothing here")]);
+        assert!(matches!(extract_demo(&zip), Err(Error::NoDemoInZip(_))));
+    }
+}
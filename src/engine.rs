@@ -1,7 +1,9 @@
 use std::collections::HashMap;
 use std::fs::File;
+use std::path::Path;
 use std::path::PathBuf;
 
+use indexmap::IndexMap;
 use indoc::indoc;
 use log::info;
 use log::trace;
@@ -11,12 +13,18 @@ use serde::Serialize;
 
 const EXAMPLE_ENGINES_FILE: &str = indoc!(
     r#"
+    # The engine to use when none is given with -e. Without this, the first
+    # engine in this file is used.
+    # default = "example"
+
     # This header should be the canonical name of your engine.
     [example]
     # Put here any aliases that you want to use with the -e option.
     aliases = ["example", "ex"]
     # Path to the binary.
     binary = "/dev/zero"
+    # A short description of the engine, shown with --engine-info.
+    # description = "An example engine"
     # What compatibility levels does this engine support?
     # Valid values: ["Vanilla", "Boom", "MBF", "Eternity", "ZDoom"]
     kind = "Vanilla"
@@ -25,13 +33,38 @@ const EXAMPLE_ENGINES_FILE: &str = indoc!(
     supports_widescreen_assets = false
     # Are there any extra arguments that this engine needs in all cases?
     required_args = []
+    # The arguments that make the engine print its version and exit, if the
+    # ones guessed from its kind are wrong. --engine-info only shows the
+    # version if these are set.
+    # version_args = ["-version"]
+    # Where to run the engine from, if not the directory the binary is in.
+    # working_dir = "/usr/share/games/example"
+    # Environment variables to set for the engine. ${VAR} is replaced with the
+    # value of VAR in the environment this program runs in.
+    # [example.env]
+    # SDL_VIDEODRIVER = "x11"
+    # The arguments to pass for options like --pistol-start, if the ones
+    # guessed from the kind of engine are wrong. An empty list means the
+    # engine doesn't support the option.
+    # [example.option_args]
+    # pistol-start = ["-pistolstart"]
     "#
 );
 
+use crate::demo::DemoFormat;
+use crate::geometry::Geometry;
 use crate::util::absolute_path;
+use crate::util::collect_errors;
+use crate::util::edit_distance;
+use crate::util::read_error;
+use crate::util::toml_error;
+use crate::util::write_error;
 use crate::Error;
 
-#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+/// The video modes that Boom-derived engines accept for `-vidmode`.
+pub(crate) const VIDEO_MODES: &[&str] = &["8", "15", "16", "32", "gl"];
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
 #[allow(clippy::upper_case_acronyms)]
 pub(crate) enum DoomEngineKind {
     Vanilla,
@@ -41,45 +74,353 @@ pub(crate) enum DoomEngineKind {
     ZDoom,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+impl DoomEngineKind {
+    /// Every kind, in the order they're offered when adding an engine.
+    pub const ALL: [DoomEngineKind; 5] = [
+        Self::Vanilla,
+        Self::Boom,
+        Self::MBF,
+        Self::Eternity,
+        Self::ZDoom,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Vanilla => "Vanilla",
+            Self::Boom => "Boom",
+            Self::MBF => "MBF",
+            Self::Eternity => "Eternity",
+            Self::ZDoom => "ZDoom",
+        }
+    }
+
+    /// The arguments that set the compatibility level, for engines that have
+    /// compatibility levels. Eternity has none of its own, and the others
+    /// would reject `-complevel` or silently ignore it.
+    pub fn complevel_args(self, level: u8) -> Option<Vec<String>> {
+        match self {
+            Self::Boom | Self::MBF => Some(vec!["-complevel".to_owned(), level.to_string()]),
+            Self::Vanilla | Self::Eternity | Self::ZDoom => None,
+        }
+    }
+
+    /// The arguments that set the video mode (software or hardware rendering).
+    pub fn video_mode_args(self, mode: &str) -> Option<Vec<String>> {
+        match self {
+            Self::Boom | Self::MBF => Some(vec!["-vidmode".to_owned(), mode.to_owned()]),
+            Self::Vanilla | Self::Eternity | Self::ZDoom => None,
+        }
+    }
+
+    /// The arguments that set the screen resolution.
+    pub fn geometry_args(self, geometry: Geometry) -> Option<Vec<String>> {
+        match self {
+            Self::Boom | Self::MBF => Some(vec!["-geom".to_owned(), geometry.to_string()]),
+            Self::ZDoom => Some(vec![
+                "-width".to_owned(),
+                geometry.width.to_string(),
+                "-height".to_owned(),
+                geometry.height.to_string(),
+            ]),
+            Self::Vanilla | Self::Eternity => None,
+        }
+    }
+
+    /// The arguments that make the game run fullscreen or in a window.
+    pub fn fullscreen_args(self, fullscreen: bool) -> Option<Vec<String>> {
+        match self {
+            Self::Vanilla | Self::Boom | Self::MBF => Some(vec![if fullscreen {
+                "-fullscreen".to_owned()
+            } else {
+                "-window".to_owned()
+            }]),
+            Self::ZDoom => Some(vec![
+                "+vid_fullscreen".to_owned(),
+                (fullscreen as u8).to_string(),
+            ]),
+            Self::Eternity => None,
+        }
+    }
+
+    /// The arguments that set the skill level, given from 1 to 5. Anything
+    /// below 1 is taken as 1.
+    pub fn skill_args(self, skill: u8) -> Vec<String> {
+        let skill = skill.max(1);
+        match self {
+            // ZDoom counts skill levels from 0.
+            Self::ZDoom => vec!["+skill".to_owned(), (skill - 1).to_string()],
+            Self::Vanilla | Self::Boom | Self::MBF | Self::Eternity => {
+                vec!["-skill".to_owned(), skill.to_string()]
+            }
+        }
+    }
+
+    /// The argument that sets the directory savegames are kept in.
+    pub fn save_dir_arg(self) -> &'static str {
+        match self {
+            Self::ZDoom => "-savedir",
+            Self::Vanilla | Self::Boom | Self::MBF | Self::Eternity => "-save",
+        }
+    }
+
+    /// Whether engines of this kind have console variables, set with `+name value`.
+    pub fn has_cvars(self) -> bool {
+        match self {
+            Self::ZDoom => true,
+            Self::Vanilla | Self::Boom | Self::MBF | Self::Eternity => false,
+        }
+    }
+
+    /// Whether engines of this kind understand `-longtics` and `-shorttics`,
+    /// to record demos with finer turning or play with vanilla's coarse turning.
+    ///
+    /// | Kind     | Long tics |
+    /// |----------|-----------|
+    /// | Vanilla  | no        |
+    /// | Boom     | yes       |
+    /// | MBF      | yes       |
+    /// | Eternity | yes       |
+    /// | ZDoom    | no        |
+    pub fn has_longtics(self) -> bool {
+        match self {
+            Self::Boom | Self::MBF | Self::Eternity => true,
+            Self::Vanilla | Self::ZDoom => false,
+        }
+    }
+
+    /// Whether engines of this kind can load a directory with `-file`, like a PK3.
+    pub fn can_mount_dirs(self) -> bool {
+        match self {
+            Self::ZDoom => true,
+            Self::Vanilla | Self::Boom | Self::MBF | Self::Eternity => false,
+        }
+    }
+
+    /// Whether engines of this kind can continue recording a demo in `format`.
+    pub fn can_continue(self, format: DemoFormat) -> bool {
+        match self {
+            Self::Boom | Self::MBF => true,
+            Self::Vanilla => matches!(format, DemoFormat::Doom12 | DemoFormat::Vanilla),
+            Self::Eternity => !matches!(format, DemoFormat::PrBoom),
+            Self::ZDoom => false,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Vanilla" => Some(Self::Vanilla),
+            "Boom" => Some(Self::Boom),
+            "MBF" => Some(Self::MBF),
+            "Eternity" => Some(Self::Eternity),
+            "ZDoom" => Some(Self::ZDoom),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug)]
 pub(crate) struct DoomEngine {
     aliases: Vec<String>,
     pub binary: PathBuf,
+    /// What the engine is, for people choosing between engines.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
     pub kind: DoomEngineKind,
     pub supports_widescreen_assets: bool,
     pub required_args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    version_args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    working_dir: Option<PathBuf>,
+    /// Environment variables to run the engine with, which may refer to our
+    /// own as `${VAR}`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// The arguments to pass for some options (by long name), overriding
+    /// those guessed from the engine's kind.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    option_args: HashMap<String, Vec<String>>,
+}
+
+impl DoomEngine {
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    pub fn new(name: &str, binary: PathBuf, kind: DoomEngineKind) -> Self {
+        Self {
+            aliases: vec![name.to_owned()],
+            binary,
+            description: String::new(),
+            kind,
+            supports_widescreen_assets: false,
+            required_args: vec![],
+            version_args: vec![],
+            working_dir: None,
+            env: HashMap::new(),
+            option_args: HashMap::new(),
+        }
+    }
+
+    /// The arguments that make each level start from a pistol start. Crispy
+    /// Doom is the only vanilla engine with them.
+    pub fn pistol_start_args(&self) -> Option<Vec<String>> {
+        self.args_for("pistol-start", || match self.kind {
+            DoomEngineKind::Boom | DoomEngineKind::MBF => Some(vec!["-pistolstart".to_owned()]),
+            DoomEngineKind::Vanilla if self.binary_name_contains("crispy") => {
+                Some(vec!["-pistolstart".to_owned()])
+            }
+            DoomEngineKind::Vanilla | DoomEngineKind::Eternity | DoomEngineKind::ZDoom => None,
+        })
+    }
+
+    /// The arguments that place things as in co-op, as DSDA Doom and Woof do.
+    pub fn coop_spawns_args(&self) -> Option<Vec<String>> {
+        self.args_for("coop-spawns", || match self.kind {
+            DoomEngineKind::Boom | DoomEngineKind::MBF => Some(vec!["-coop_spawns".to_owned()]),
+            DoomEngineKind::Vanilla | DoomEngineKind::Eternity | DoomEngineKind::ZDoom => None,
+        })
+    }
+
+    /// The arguments that start a single-player game under network game
+    /// rules, so that co-op things appear.
+    pub fn solo_net_args(&self) -> Option<Vec<String>> {
+        self.args_for("solo-net", || match self.kind {
+            DoomEngineKind::Vanilla
+            | DoomEngineKind::Boom
+            | DoomEngineKind::MBF
+            | DoomEngineKind::Eternity => Some(vec!["-solo-net".to_owned()]),
+            DoomEngineKind::ZDoom => None,
+        })
+    }
+
+    /// Whether `version_args` were configured, so that the engine is known to
+    /// print its version and exit rather than start a game.
+    pub fn has_version_args(&self) -> bool {
+        !self.version_args.is_empty()
+    }
+
+    /// The arguments that make the engine print its version and exit: those
+    /// configured, or else the usual ones for its kind.
+    pub fn version_args(&self) -> Vec<String> {
+        if !self.version_args.is_empty() {
+            return self.version_args.clone();
+        }
+        match self.kind {
+            DoomEngineKind::Boom | DoomEngineKind::MBF => vec!["-v".to_owned()],
+            DoomEngineKind::Vanilla | DoomEngineKind::Eternity | DoomEngineKind::ZDoom => {
+                vec!["-version".to_owned()]
+            }
+        }
+    }
+
+    /// The arguments configured for `option`, or else `guess`'s. No arguments
+    /// at all means the option isn't supported.
+    fn args_for(
+        &self,
+        option: &str,
+        guess: impl FnOnce() -> Option<Vec<String>>,
+    ) -> Option<Vec<String>> {
+        match self.option_args.get(option) {
+            Some(args) if args.is_empty() => None,
+            Some(args) => Some(args.clone()),
+            None => guess(),
+        }
+    }
+
+    fn binary_name_contains(&self, name: &str) -> bool {
+        self.binary
+            .file_stem()
+            .is_some_and(|stem| stem.to_string_lossy().to_lowercase().contains(name))
+    }
+
+    /// The directory the engine is run from: `working_dir` if configured, or
+    /// the one its binary is in, where it's most likely to find its own files.
+    pub fn working_dir(&self) -> &Path {
+        self.working_dir
+            .as_deref()
+            .or_else(|| self.binary.parent())
+            .unwrap_or_else(|| Path::new("."))
+    }
+}
+
+/// The engines file: a table for each engine, keyed by its canonical name and
+/// kept in the order written, and optionally the name of the engine to use by
+/// default.
+#[derive(Deserialize, Serialize)]
+struct EnginesFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default: Option<String>,
+    #[serde(flatten)]
+    engines: IndexMap<String, DoomEngine>,
 }
 
 pub(crate) struct KnownEngines {
     alias_map: HashMap<String, usize>,
+    names: Vec<String>,
     engines: Vec<DoomEngine>,
+    /// The name or alias of the engine to use when none is given.
+    default: Option<String>,
 }
 
-pub(crate) struct KnownEnginesIterator {
-    iter: Box<dyn Iterator<Item = String>>,
-}
+impl KnownEngines {
+    /// Builds the known engines from `engine_map`, keeping its order.
+    ///
+    /// Every name and alias has to belong to only one engine, or which engine
+    /// `-e` picks would be up to chance.
+    pub fn new(engine_map: IndexMap<String, DoomEngine>) -> Result<Self, Error> {
+        let mut known = Self {
+            alias_map: HashMap::new(),
+            names: Vec::new(),
+            engines: Vec::new(),
+            default: None,
+        };
+        for (name, engine) in engine_map {
+            known.check_aliases(&name, &engine, None)?;
+            known.push(name, engine);
+        }
+        Ok(known)
+    }
 
-impl Iterator for KnownEnginesIterator {
-    type Item = String;
+    /// Checks that neither `name` nor any of `engine`'s aliases already
+    /// belongs to an engine other than the one at `replacing`.
+    fn check_aliases(
+        &self,
+        name: &str,
+        engine: &DoomEngine,
+        replacing: Option<usize>,
+    ) -> Result<(), Error> {
+        for alias in std::iter::once(name).chain(engine.aliases.iter().map(String::as_str)) {
+            match self.alias_map.get(alias) {
+                Some(&other) if Some(other) != replacing => {
+                    return Err(Error::DuplicateAlias {
+                        alias: alias.to_owned(),
+                        engines: (self.names[other].clone(), name.to_owned()),
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next()
+    fn push(&mut self, name: String, engine: DoomEngine) {
+        let i = self.engines.len();
+        self.alias_map.insert(name.clone(), i);
+        for alias in engine.aliases.iter() {
+            self.alias_map.insert(alias.clone(), i);
+        }
+        self.names.push(name);
+        self.engines.push(engine);
     }
-}
 
-impl KnownEngines {
-    pub fn new(engine_map: HashMap<String, DoomEngine>) -> Self {
-        let mut alias_map = HashMap::new();
-        let mut engines = Vec::new();
-        for (name, eng) in engine_map {
-            let i = engines.len();
-            alias_map.insert(name, i);
-            for alias in eng.aliases.iter() {
-                alias_map.insert(alias.clone(), i);
-            }
-            engines.push(eng);
+    /// The canonical name of the engine to use when none is given: the one
+    /// named by `default` in the engines file, or else the first.
+    pub fn default_name(&self) -> Option<&str> {
+        match &self.default {
+            Some(default) => self.get_named(default).map(|(name, _)| name),
+            None => self.names.first().map(String::as_str),
         }
-        Self { alias_map, engines }
     }
 
     pub fn get(&self, name: &str) -> Option<&DoomEngine> {
@@ -87,50 +428,443 @@ impl KnownEngines {
         Some(&self.engines[index])
     }
 
-    pub fn iter(&'_ self) -> KnownEnginesIterator {
-        let engines = self.engines.clone();
-        KnownEnginesIterator {
-            iter: Box::new(
-                engines
-                    .into_iter()
-                    .map(|e| e.aliases)
-                    .flat_map(|ss| ss.into_iter()),
-            ),
+    /// Like `get`, but also returns the engine's canonical name.
+    pub fn get_named(&self, name: &str) -> Option<(&str, &DoomEngine)> {
+        let index = *self.alias_map.get(name)?;
+        Some((&self.names[index], &self.engines[index]))
+    }
+
+    /// Suggests the known name or alias closest to a misspelled `name`.
+    pub fn suggest(&self, name: &str) -> Option<&str> {
+        self.alias_map
+            .keys()
+            .map(|known| (edit_distance(name, known), known))
+            .filter(|(distance, known)| *distance <= known.len() / 2 + 1)
+            .min()
+            .map(|(_, known)| known.as_str())
+    }
+
+    /// The canonical name of the first engine of `kind`, if there is one.
+    pub fn first_of_kind(&self, kind: DoomEngineKind) -> Option<&str> {
+        self.iter_engines()
+            .find(|(_, engine)| engine.kind == kind)
+            .map(|(name, _)| name)
+    }
+
+    /// Iterates over each engine once, along with its canonical name.
+    pub fn iter_engines(&self) -> impl Iterator<Item = (&str, &DoomEngine)> {
+        self.names
+            .iter()
+            .map(String::as_str)
+            .zip(self.engines.iter())
+    }
+
+    /// Adds `engine` under the canonical name `name`, replacing the engine
+    /// with that canonical name if there is one. Neither `name` nor any of
+    /// the engine's aliases may belong to another engine.
+    pub fn insert(&mut self, name: String, engine: DoomEngine) -> Result<(), Error> {
+        let existing = self.names.iter().position(|known| *known == name);
+        self.check_aliases(&name, &engine, existing)?;
+        match existing {
+            Some(index) => {
+                self.alias_map.retain(|_, i| *i != index);
+                self.alias_map.insert(name, index);
+                for alias in engine.aliases.iter() {
+                    self.alias_map.insert(alias.clone(), index);
+                }
+                self.engines[index] = engine;
+            }
+            None => self.push(name, engine),
         }
+        Ok(())
+    }
+
+    /// Removes the engine known by `name` (or any of its aliases), along with
+    /// all of its other aliases.
+    pub fn remove(&mut self, name: &str) -> Option<(String, DoomEngine)> {
+        let index = self.alias_map.remove(name)?;
+        self.alias_map.retain(|_, i| *i != index);
+        for i in self.alias_map.values_mut() {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+        if let Some(default) = &self.default {
+            if !self.alias_map.contains_key(default) {
+                self.default = None;
+            }
+        }
+        Some((self.names.remove(index), self.engines.remove(index)))
     }
 }
 
+pub(crate) fn engines_path() -> Result<PathBuf, Error> {
+    crate::config_dir().map(|d| d.join("engines.toml"))
+}
+
 pub(crate) fn read_known_engines() -> Result<KnownEngines, Error> {
-    let engines_json_path = crate::doom_dir()?.join("engines.toml");
+    read_engines_file(&engines_path()?)
+}
+
+/// Reads the engines file at `engines_json_path`, writing the template there
+/// first if it doesn't exist.
+fn read_engines_file(engines_json_path: &Path) -> Result<KnownEngines, Error> {
     trace!(
         "Searching for Doom engine definitions in {}",
         engines_json_path.to_string_lossy()
     );
     if !engines_json_path.exists() {
         warn!("Path not found, creating template. Please fill out this template.");
-        let mut f = File::create(&engines_json_path).map_err(Error::Io)?;
+        let mut f = File::create(engines_json_path).map_err(write_error(engines_json_path))?;
 
         use std::io::Write;
-        write!(f, "{}", EXAMPLE_ENGINES_FILE).map_err(Error::Io)?;
+        write!(f, "{}", EXAMPLE_ENGINES_FILE).map_err(write_error(engines_json_path))?;
     }
 
-    let engines: HashMap<String, DoomEngine> = toml::from_slice(
-        &std::fs::read(engines_json_path.as_path()).map_err(Error::Io)?,
-    )
-    .map_err(|error| Error::BadToml {
-        file: engines_json_path,
-        error,
-    })?;
-    let engines: HashMap<String, DoomEngine> = engines
-        .into_iter()
-        .map(|(name, mut engine)| {
-            absolute_path(engine.binary.clone()).map(|binary| {
+    let contents = std::fs::read(engines_json_path).map_err(read_error(engines_json_path))?;
+    let EnginesFile { default, engines } =
+        toml::from_slice(&contents).map_err(toml_error(engines_json_path, &contents))?;
+    let engines: IndexMap<String, DoomEngine> =
+        collect_errors(engines.into_iter().map(|(name, mut engine)| {
+            absolute_path(engine.binary.clone()).and_then(|binary| {
                 engine.binary = binary;
-                (name, engine)
+                engine.working_dir = engine.working_dir.map(absolute_path).transpose()?;
+                Ok((name, engine))
             })
-        })
-        .collect::<Result<_, _>>()?;
+        }))?
+        .into_iter()
+        .collect();
+    let mut engines = KnownEngines::new(engines)?;
+    if let Some(default) = default {
+        if engines.get(&default).is_none() {
+            return Err(Error::UnknownEngine(default));
+        }
+        engines.default = Some(default);
+    }
     info!("Found engines:");
-    engines.keys().for_each(|eng| info!("    {}", eng));
-    Ok(KnownEngines::new(engines))
+    engines.iter_engines().for_each(|(name, engine)| {
+        if engine.description.is_empty() {
+            info!("    {}", name)
+        } else {
+            info!("    {} ({})", name, engine.description)
+        }
+    });
+    Ok(engines)
+}
+
+/// Writes `engines` to `path` in the same format as the engines file, such
+/// that reading it back gives the same engines. Comments are not preserved.
+pub(crate) fn write_known_engines(engines: &KnownEngines, path: &Path) -> Result<(), Error> {
+    let engines_file = EnginesFile {
+        default: engines.default.clone(),
+        engines: engines
+            .iter_engines()
+            .map(|(name, engine)| (name.to_owned(), engine.clone()))
+            .collect(),
+    };
+    let contents = toml::to_string(&engines_file).map_err(Error::TomlSerialize)?;
+    std::fs::write(path, contents).map_err(write_error(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn complevel_args_per_kind() {
+        for kind in DoomEngineKind::ALL {
+            let expected = match kind {
+                DoomEngineKind::Boom | DoomEngineKind::MBF => Some(strings(&["-complevel", "9"])),
+                DoomEngineKind::Vanilla | DoomEngineKind::Eternity | DoomEngineKind::ZDoom => None,
+            };
+            assert_eq!(kind.complevel_args(9), expected, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn skill_args_per_kind() {
+        for kind in DoomEngineKind::ALL {
+            let expected = match kind {
+                DoomEngineKind::ZDoom => ["+skill", "3"],
+                _ => ["-skill", "4"],
+            };
+            assert_eq!(kind.skill_args(4), expected, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn zdoom_skills_count_from_zero() {
+        assert_eq!(DoomEngineKind::ZDoom.skill_args(1), ["+skill", "0"]);
+        assert_eq!(DoomEngineKind::ZDoom.skill_args(5), ["+skill", "4"]);
+        assert_eq!(DoomEngineKind::ZDoom.skill_args(0), ["+skill", "0"]);
+        assert_eq!(DoomEngineKind::Boom.skill_args(0), ["-skill", "1"]);
+    }
+
+    #[test]
+    fn geometry_args_per_kind() {
+        let geometry = Geometry::parse("1920x1080").unwrap();
+        for kind in DoomEngineKind::ALL {
+            let expected = match kind {
+                DoomEngineKind::Boom | DoomEngineKind::MBF => {
+                    Some(strings(&["-geom", "1920x1080"]))
+                }
+                DoomEngineKind::ZDoom => Some(strings(&["-width", "1920", "-height", "1080"])),
+                DoomEngineKind::Vanilla | DoomEngineKind::Eternity => None,
+            };
+            assert_eq!(kind.geometry_args(geometry), expected, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn video_mode_args_per_kind() {
+        for kind in DoomEngineKind::ALL {
+            let expected = match kind {
+                DoomEngineKind::Boom | DoomEngineKind::MBF => Some(strings(&["-vidmode", "GL"])),
+                DoomEngineKind::Vanilla | DoomEngineKind::Eternity | DoomEngineKind::ZDoom => None,
+            };
+            assert_eq!(kind.video_mode_args("GL"), expected, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn fullscreen_per_kind() {
+        assert_eq!(
+            DoomEngineKind::MBF.fullscreen_args(false).unwrap(),
+            ["-window"]
+        );
+        assert_eq!(
+            DoomEngineKind::ZDoom.fullscreen_args(true).unwrap(),
+            ["+vid_fullscreen", "1"]
+        );
+        assert!(DoomEngineKind::Eternity.fullscreen_args(true).is_none());
+    }
+
+    #[test]
+    fn save_dir_spelling() {
+        assert_eq!(DoomEngineKind::Boom.save_dir_arg(), "-save");
+        assert_eq!(DoomEngineKind::MBF.save_dir_arg(), "-save");
+        assert_eq!(DoomEngineKind::ZDoom.save_dir_arg(), "-savedir");
+    }
+
+    fn engine(name: &str, aliases: &[&str], kind: DoomEngineKind) -> DoomEngine {
+        let mut engine = DoomEngine::new(name, PathBuf::from("/usr/games").join(name), kind);
+        engine.aliases = aliases.iter().map(|&alias| alias.to_owned()).collect();
+        engine
+    }
+
+    #[test]
+    fn shared_alias_is_rejected() {
+        let mut engine_map = IndexMap::new();
+        engine_map.insert(
+            "prboom-plus".to_owned(),
+            engine("prboom-plus", &["prboom-plus", "pb"], DoomEngineKind::Boom),
+        );
+        engine_map.insert(
+            "dsda-doom".to_owned(),
+            engine("dsda-doom", &["dsda", "pb"], DoomEngineKind::MBF),
+        );
+        match KnownEngines::new(engine_map) {
+            Err(Error::DuplicateAlias { alias, engines }) => {
+                assert_eq!(alias, "pb");
+                assert_eq!(engines, ("prboom-plus".to_owned(), "dsda-doom".to_owned()));
+            }
+            _ => panic!("expected a duplicate alias error"),
+        }
+    }
+
+    #[test]
+    fn alias_naming_another_engine_is_rejected() {
+        let mut engine_map = IndexMap::new();
+        engine_map.insert(
+            "woof".to_owned(),
+            engine("woof", &["woof"], DoomEngineKind::MBF),
+        );
+        engine_map.insert(
+            "dsda-doom".to_owned(),
+            engine("dsda-doom", &["woof"], DoomEngineKind::MBF),
+        );
+        assert!(matches!(
+            KnownEngines::new(engine_map),
+            Err(Error::DuplicateAlias { .. })
+        ));
+    }
+
+    #[test]
+    fn insert_rejects_shared_alias() {
+        let mut engine_map = IndexMap::new();
+        engine_map.insert(
+            "prboom-plus".to_owned(),
+            engine("prboom-plus", &["pb"], DoomEngineKind::Boom),
+        );
+        let mut engines = KnownEngines::new(engine_map).unwrap();
+        let result = engines.insert(
+            "dsda-doom".to_owned(),
+            engine("dsda-doom", &["pb"], DoomEngineKind::MBF),
+        );
+        assert!(matches!(result, Err(Error::DuplicateAlias { .. })));
+        assert_eq!(engines.get_named("pb").unwrap().0, "prboom-plus");
+        assert!(engines.get("dsda-doom").is_none());
+    }
+
+    fn engine_list(engines: &KnownEngines) -> Vec<(String, DoomEngine)> {
+        engines
+            .iter_engines()
+            .map(|(name, engine)| (name.to_owned(), engine.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn insert_by_alias_is_rejected() {
+        let mut engine_map = IndexMap::new();
+        engine_map.insert(
+            "prboom-plus".to_owned(),
+            engine("prboom-plus", &["pb"], DoomEngineKind::Boom),
+        );
+        let mut engines = KnownEngines::new(engine_map).unwrap();
+        let result = engines.insert("pb".to_owned(), engine("pb", &[], DoomEngineKind::Vanilla));
+        assert!(matches!(result, Err(Error::DuplicateAlias { .. })));
+        assert_eq!(engines.get_named("pb").unwrap().0, "prboom-plus");
+    }
+
+    #[test]
+    fn insert_replaces_canonical_name_in_place() {
+        let mut engine_map = IndexMap::new();
+        engine_map.insert(
+            "prboom-plus".to_owned(),
+            engine("prboom-plus", &["pb"], DoomEngineKind::Boom),
+        );
+        engine_map.insert("woof".to_owned(), engine("woof", &[], DoomEngineKind::MBF));
+        let mut engines = KnownEngines::new(engine_map).unwrap();
+        engines
+            .insert(
+                "prboom-plus".to_owned(),
+                engine("prboom-plus", &["glboom"], DoomEngineKind::Boom),
+            )
+            .unwrap();
+        let names: Vec<_> = engines.iter_engines().map(|(name, _)| name).collect();
+        assert_eq!(names, ["prboom-plus", "woof"]);
+        assert!(engines.get("pb").is_none());
+        assert_eq!(engines.get_named("glboom").unwrap().0, "prboom-plus");
+        assert_eq!(engines.get_named("woof").unwrap().0, "woof");
+    }
+
+    #[test]
+    fn add_and_remove_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("engines.toml");
+        let template = read_engines_file(&path).unwrap();
+
+        let mut engines = read_engines_file(&path).unwrap();
+        engines
+            .insert(
+                "woof".to_owned(),
+                engine("woof", &["wf"], DoomEngineKind::MBF),
+            )
+            .unwrap();
+        write_known_engines(&engines, &path).unwrap();
+        let added = read_engines_file(&path).unwrap();
+        assert_eq!(engine_list(&added), engine_list(&engines));
+        assert_eq!(added.get_named("wf").unwrap().0, "woof");
+
+        let mut engines = added;
+        assert!(engines.remove("woof").is_some());
+        write_known_engines(&engines, &path).unwrap();
+        let removed = read_engines_file(&path).unwrap();
+        assert_eq!(engine_list(&removed), engine_list(&template));
+    }
+
+    #[test]
+    fn coop_spawns_only_on_boom_engines() {
+        for kind in DoomEngineKind::ALL {
+            let expected = match kind {
+                DoomEngineKind::Boom | DoomEngineKind::MBF => Some(strings(&["-coop_spawns"])),
+                DoomEngineKind::Vanilla | DoomEngineKind::Eternity | DoomEngineKind::ZDoom => None,
+            };
+            assert_eq!(
+                engine("engine", &[], kind).coop_spawns_args(),
+                expected,
+                "{:?}",
+                kind
+            );
+        }
+
+        let mut prboom_plus = engine("prboom-plus", &[], DoomEngineKind::Boom);
+        prboom_plus
+            .option_args
+            .insert("coop-spawns".to_owned(), vec![]);
+        assert_eq!(prboom_plus.coop_spawns_args(), None);
+    }
+
+    #[test]
+    fn solo_net_everywhere_but_zdoom() {
+        for kind in DoomEngineKind::ALL {
+            let expected = match kind {
+                DoomEngineKind::ZDoom => None,
+                _ => Some(strings(&["-solo-net"])),
+            };
+            assert_eq!(
+                engine("engine", &[], kind).solo_net_args(),
+                expected,
+                "{:?}",
+                kind
+            );
+        }
+
+        // A ZDoom fork that has it can say so in the engines file.
+        let mut fork = engine("fork", &[], DoomEngineKind::ZDoom);
+        fork.option_args
+            .insert("solo-net".to_owned(), strings(&["-solo-net"]));
+        assert_eq!(fork.solo_net_args(), Some(strings(&["-solo-net"])));
+    }
+
+    #[test]
+    fn first_of_kind_picks_the_first_declared() {
+        let mut engine_map = IndexMap::new();
+        for (name, kind) in [
+            ("gzdoom", DoomEngineKind::ZDoom),
+            ("chocolate-doom", DoomEngineKind::Vanilla),
+            ("prboom-plus", DoomEngineKind::Boom),
+            ("dsda-doom", DoomEngineKind::Boom),
+        ] {
+            engine_map.insert(name.to_owned(), engine(name, &[], kind));
+        }
+        let engines = KnownEngines::new(engine_map).unwrap();
+        assert_eq!(
+            engines.first_of_kind(DoomEngineKind::Boom),
+            Some("prboom-plus")
+        );
+        assert_eq!(engines.first_of_kind(DoomEngineKind::ZDoom), Some("gzdoom"));
+        assert_eq!(engines.first_of_kind(DoomEngineKind::MBF), None);
+    }
+
+    #[test]
+    fn engines_keep_declaration_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("engines.toml");
+        let table = |name: &str, kind: &str| {
+            format!(
+                "[{}]\naliases = []\nbinary = \"/usr/games/{}\"\nkind = \"{}\"\n\
+                 supports_widescreen_assets = false\nrequired_args = []\n\n",
+                name, name, kind
+            )
+        };
+        std::fs::write(
+            &path,
+            [
+                table("woof", "MBF"),
+                table("gzdoom", "ZDoom"),
+                table("prboom-plus", "Boom"),
+            ]
+            .concat(),
+        )
+        .unwrap();
+
+        let engines = read_engines_file(&path).unwrap();
+        let names: Vec<_> = engines.iter_engines().map(|(name, _)| name).collect();
+        assert_eq!(names, ["woof", "gzdoom", "prboom-plus"]);
+    }
 }
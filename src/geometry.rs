@@ -0,0 +1,107 @@
+use std::fmt;
+
+use crate::Error;
+
+/// The largest width or height we accept, to catch typos.
+const MAX_DIMENSION: u32 = 16384;
+
+/// A screen resolution, as given to `--geometry`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) struct Geometry {
+    pub width: u32,
+    pub height: u32,
+    /// `Some(true)` for fullscreen and `Some(false)` for windowed, as set by a
+    /// trailing `F` or `W`.
+    pub fullscreen: Option<bool>,
+}
+
+impl Geometry {
+    /// Parses `WxH`, optionally followed by `F` (fullscreen) or `W` (windowed).
+    /// The `x` and the suffix may be given in either case. An X11-style
+    /// position (`WxH+X+Y`) is rejected, as no engine takes one.
+    pub fn parse(geometry: &str) -> Result<Self, Error> {
+        let bad_geometry = || Error::BadGeometry(geometry.to_owned());
+        let (dimensions, fullscreen) = match geometry.chars().last() {
+            Some('f') | Some('F') => (&geometry[..geometry.len() - 1], Some(true)),
+            Some('w') | Some('W') => (&geometry[..geometry.len() - 1], Some(false)),
+            _ => (geometry, None),
+        };
+        let mut dimensions = dimensions.splitn(2, ['x', 'X']);
+        let mut dimension = || {
+            dimensions
+                .next()
+                .and_then(|d| d.parse::<u32>().ok())
+                .filter(|d| (1..=MAX_DIMENSION).contains(d))
+                .ok_or_else(bad_geometry)
+        };
+        let width = dimension()?;
+        let height = dimension()?;
+        Ok(Self {
+            width,
+            height,
+            fullscreen,
+        })
+    }
+}
+
+impl fmt::Display for Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        match self.fullscreen {
+            Some(true) => write!(f, "f"),
+            Some(false) => write!(f, "w"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_width_and_height() {
+        let geometry = Geometry::parse("1920x1080").unwrap();
+        assert_eq!((geometry.width, geometry.height), (1920, 1080));
+        assert_eq!(geometry.fullscreen, None);
+        assert_eq!(Geometry::parse("1920X1080").unwrap(), geometry);
+        assert_eq!(geometry.to_string(), "1920x1080");
+    }
+
+    #[test]
+    fn parses_fullscreen_suffix() {
+        assert_eq!(Geometry::parse("640x480F").unwrap().fullscreen, Some(true));
+        assert_eq!(Geometry::parse("640x480w").unwrap().fullscreen, Some(false));
+        assert_eq!(Geometry::parse("640x480f").unwrap().to_string(), "640x480f");
+    }
+
+    #[test]
+    fn rejects_window_position() {
+        assert!(matches!(
+            Geometry::parse("1920x1080+0+0"),
+            Err(Error::BadGeometry(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_geometry() {
+        for geometry in [
+            "foo",
+            "",
+            "1920",
+            "1920x",
+            "x1080",
+            "0x480",
+            "640x0",
+            "99999x480",
+            "-640x480",
+            "640x480x2",
+        ] {
+            assert!(
+                matches!(Geometry::parse(geometry), Err(Error::BadGeometry(_))),
+                "{}",
+                geometry
+            );
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::path::Path;
+
+use log::info;
+use log::warn;
+
+use crate::util::read_error;
+use crate::Error;
+
+/// The game an IWAD belongs to, which decides how some arguments are spelled.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) enum Game {
+    Doom,
+    Doom2,
+    Heretic,
+    Hexen,
+    Strife,
+}
+
+impl Game {
+    /// Whether maps are numbered by episode and map (`-warp E M`) rather than
+    /// by map alone (`-warp MAP`).
+    pub fn is_episodic(self) -> bool {
+        matches!(self, Self::Doom | Self::Heretic)
+    }
+
+    pub fn is_doom(self) -> bool {
+        matches!(self, Self::Doom | Self::Doom2)
+    }
+
+    /// Builds the `-warp` arguments for `warp`, the level as given by the user.
+    pub fn warp_args<'w>(self, warp: &[&'w str]) -> Result<Vec<&'w str>, Error> {
+        let bad_warp = || Error::BadWarp(warp.join(" "));
+        let numbers = warp
+            .iter()
+            .map(|w| w.parse::<u8>().map_err(|_| bad_warp()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match numbers[..] {
+            [_] => Ok(vec!["-warp", warp[0]]),
+            [episode, map] if self.is_episodic() => {
+                if (1..=self.episodes()).contains(&episode) && (1..=9).contains(&map) {
+                    Ok(vec!["-warp", warp[0], warp[1]])
+                } else {
+                    Err(bad_warp())
+                }
+            }
+            _ => Err(bad_warp()),
+        }
+    }
+
+    /// Builds a warp, as given to `warp_args`, from `--episode` and `--map`.
+    pub fn episode_map_warp<'w>(
+        self,
+        episode: Option<&'w str>,
+        map: Option<&'w str>,
+    ) -> Option<Vec<&'w str>> {
+        match (episode, map) {
+            (None, None) => None,
+            (episode, map) if self.is_episodic() => {
+                Some(vec![episode.unwrap_or("1"), map.unwrap_or("1")])
+            }
+            (episode, map) => {
+                if episode.is_some() {
+                    warn!("This game has no episodes, so --episode is ignored.");
+                }
+                map.map(|map| vec![map])
+            }
+        }
+    }
+
+    /// Names the level `warp` starts on, as in `map01` or `e1m1`, or `demo` if
+    /// there's no valid level given.
+    pub fn level_name(self, warp: &[&str]) -> String {
+        let numbers = warp
+            .iter()
+            .map(|w| w.parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_default();
+        match numbers[..] {
+            [episode, map] => format!("e{}m{}", episode, map),
+            [map] if self.is_episodic() => format!("e1m{}", map),
+            [map] => format!("map{:02}", map),
+            _ => "demo".to_owned(),
+        }
+    }
+
+    fn episodes(self) -> u8 {
+        match self {
+            Self::Doom => 4,
+            Self::Heretic => 6,
+            _ => 1,
+        }
+    }
+}
+
+pub(crate) fn detect_game(iwad: &Path) -> Game {
+    let stem = iwad
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match stem.as_str() {
+        "heretic" | "heretic1" => Game::Heretic,
+        "hexen" | "hexdd" => Game::Hexen,
+        "strife0" | "strife1" => Game::Strife,
+        s if DOOM.contains(&s) => Game::Doom,
+        _ => Game::Doom2,
+    }
+}
+
+const DOOM: &[&str] = &["doom", "doomu", "doom1", "freedoom1"];
+const DOOM2: &[&str] = &["doom2", "tnt", "plutonia", "freedoom2"];
+
+/// Well-known PWADs that only work with one family of IWADs, by lowercase
+/// file stem.
+const REQUIRED_IWADS: &[(&str, &[&str], &str)] = &[
+    ("sigil", DOOM, "Doom"),
+    ("sigil_v1_2", DOOM, "Doom"),
+    ("sigil_v1_21", DOOM, "Doom"),
+    ("sigil_compat", DOOM, "Doom"),
+    ("av", DOOM2, "Doom 2"),
+    ("btsx_e1a", DOOM2, "Doom 2"),
+    ("btsx_e2a", DOOM2, "Doom 2"),
+    ("eviternity", DOOM2, "Doom 2"),
+    ("hr", DOOM2, "Doom 2"),
+    ("hr2final", DOOM2, "Doom 2"),
+    ("mm", DOOM2, "Doom 2"),
+    ("mm2", DOOM2, "Doom 2"),
+    ("nerve", DOOM2, "Doom 2"),
+    ("pl2", DOOM2, "Doom 2"),
+    ("requiem", DOOM2, "Doom 2"),
+    ("scythe", DOOM2, "Doom 2"),
+    ("scythe2", DOOM2, "Doom 2"),
+    ("sunlust", DOOM2, "Doom 2"),
+    ("valiant", DOOM2, "Doom 2"),
+];
+
+/// If `pwad` is known to need a different IWAD than `iwad` (a lowercase
+/// stem), returns the name of the game it needs.
+pub(crate) fn iwad_mismatch(pwad: &Path, iwad: &str) -> Option<&'static str> {
+    let stem = pwad.file_stem()?.to_string_lossy().to_lowercase();
+    REQUIRED_IWADS
+        .iter()
+        .find(|(name, _, _)| *name == stem)
+        .filter(|(_, iwads, _)| !iwads.contains(&iwad))
+        .map(|(_, _, game)| *game)
+}
+
+/// The MD5 hashes of the released versions of the commercial IWADs, with the
+/// lowercase stem each is found under and what it is.
+const KNOWN_IWADS: &[(&str, &str, &str)] = &[
+    (
+        "f0cefca49926d00903cf57551d901abe",
+        "doom1",
+        "Doom shareware 1.9",
+    ),
+    ("1cd63c5ddff1bf8ce844237f580e9cf3", "doom", "Doom 1.9"),
+    (
+        "c4fe9fd920207691a9f493668e0a2083",
+        "doom",
+        "The Ultimate Doom 1.9",
+    ),
+    ("25e1459ca71d321525f84628f45ca8cd", "doom2", "Doom 2 1.9"),
+    (
+        "4e158d9953c79ccf97bd0663244cc6b6",
+        "tnt",
+        "TNT: Evilution 1.9",
+    ),
+    (
+        "1d39e405bf6ee3df69a8d2646c8d5c49",
+        "tnt",
+        "TNT: Evilution (id Anthology)",
+    ),
+    (
+        "75c8cf89566741fa9d22447604053bd7",
+        "plutonia",
+        "The Plutonia Experiment 1.9",
+    ),
+    (
+        "3493be7e1e2588bc9c8b31eab2587a04",
+        "plutonia",
+        "The Plutonia Experiment (id Anthology)",
+    ),
+    ("66d686b1ed6d35ff103f15dbd30e0341", "heretic", "Heretic 1.3"),
+    ("abb033caf81e26f12a2103e1fa25453f", "hexen", "Hexen 1.1"),
+    ("2fed2031a5b03892106e0f117f17901f", "strife1", "Strife 1.2"),
+];
+
+/// What an IWAD turned out to be, going by its hash.
+#[derive(Debug, PartialEq, Eq)]
+enum IwadCheck<'k> {
+    /// A release of the IWAD it's named as.
+    Release(&'k str),
+    /// A release of a different IWAD than it's named as.
+    OtherRelease(&'k str),
+    /// Named as a known IWAD but matching none of its releases, with its hash.
+    Modified(String),
+    /// Not named as any known IWAD, with its hash.
+    Unknown(String),
+}
+
+/// Checks the IWAD at `path` against the hashes of the released IWADs, warning
+/// if it's not one of them or is a different one than its name says. A
+/// modified or corrupt IWAD plays mostly fine, but makes demos desync.
+pub(crate) fn verify_iwad(path: &Path) -> Result<(), Error> {
+    match check_iwad(path, KNOWN_IWADS)? {
+        IwadCheck::Release(release) => info!("'{}' is {}.", path.to_string_lossy(), release),
+        IwadCheck::OtherRelease(release) => warn!(
+            "'{}' is actually {}; demos recorded on another IWAD will desync.",
+            path.to_string_lossy(),
+            release
+        ),
+        IwadCheck::Modified(hash) => warn!(
+            "'{}' (MD5 {}) isn't any released version of that IWAD. It may be corrupt or modified, which makes demos desync.",
+            path.to_string_lossy(),
+            hash
+        ),
+        IwadCheck::Unknown(hash) => warn!(
+            "'{}' (MD5 {}) isn't a known IWAD, so it can't be verified.",
+            path.to_string_lossy(),
+            hash
+        ),
+    }
+    Ok(())
+}
+
+/// Checks the IWAD at `path` against `known`, laid out like `KNOWN_IWADS`.
+fn check_iwad<'k>(
+    path: &Path,
+    known: &'k [(&'k str, &'k str, &'k str)],
+) -> Result<IwadCheck<'k>, Error> {
+    let data = std::fs::read(path).map_err(read_error(path))?;
+    let hash = format!("{:x}", md5::compute(&data));
+    let stem = match path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .as_str()
+    {
+        // The Ultimate Doom is sometimes kept apart from Doom under this name.
+        "doomu" => "doom".to_owned(),
+        stem => stem.to_owned(),
+    };
+    Ok(
+        match known.iter().find(|(known_hash, _, _)| *known_hash == hash) {
+            Some((_, known_stem, release)) if *known_stem == stem => IwadCheck::Release(release),
+            Some((_, _, release)) => IwadCheck::OtherRelease(release),
+            None if known.iter().any(|(_, known_stem, _)| *known_stem == stem) => {
+                IwadCheck::Modified(hash)
+            }
+            None => IwadCheck::Unknown(hash),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warps_to_episode_and_map() {
+        assert_eq!(
+            Game::Doom.warp_args(&["2", "5"]).unwrap(),
+            ["-warp", "2", "5"]
+        );
+        assert_eq!(Game::Doom.warp_args(&["7"]).unwrap(), ["-warp", "7"]);
+        assert_eq!(Game::Doom2.warp_args(&["15"]).unwrap(), ["-warp", "15"]);
+        assert_eq!(
+            Game::Heretic.warp_args(&["6", "1"]).unwrap(),
+            ["-warp", "6", "1"]
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_warps() {
+        for warp in [
+            &["5", "1"][..],
+            &["0", "1"],
+            &["1", "0"],
+            &["1", "10"],
+            &["x"],
+        ] {
+            assert!(
+                matches!(Game::Doom.warp_args(warp), Err(Error::BadWarp(_))),
+                "{:?}",
+                warp
+            );
+        }
+        assert!(matches!(
+            Game::Doom2.warp_args(&["1", "1"]),
+            Err(Error::BadWarp(_))
+        ));
+    }
+
+    #[test]
+    fn builds_warps_from_episode_and_map() {
+        assert_eq!(
+            Game::Doom.episode_map_warp(Some("3"), None).unwrap(),
+            ["3", "1"]
+        );
+        assert_eq!(
+            Game::Doom2.episode_map_warp(Some("3"), Some("7")).unwrap(),
+            ["7"]
+        );
+        assert_eq!(Game::Doom2.episode_map_warp(None, None), None);
+    }
+
+    #[test]
+    fn names_levels() {
+        assert_eq!(Game::Doom.level_name(&["2", "5"]), "e2m5");
+        assert_eq!(Game::Doom.level_name(&["5"]), "e1m5");
+        assert_eq!(Game::Doom2.level_name(&["5"]), "map05");
+        assert_eq!(Game::Doom2.level_name(&[]), "demo");
+    }
+
+    fn episode_map_args<'a>(
+        game: Game,
+        episode: Option<&'a str>,
+        map: Option<&'a str>,
+    ) -> Vec<&'a str> {
+        let warp = game.episode_map_warp(episode, map).unwrap();
+        game.warp_args(&warp).unwrap()
+    }
+
+    #[test]
+    fn warps_to_episode_and_map_in_doom() {
+        assert_eq!(
+            episode_map_args(Game::Doom, Some("2"), Some("4")),
+            ["-warp", "2", "4"]
+        );
+        assert_eq!(
+            episode_map_args(Game::Doom, None, Some("4")),
+            ["-warp", "1", "4"]
+        );
+    }
+
+    #[test]
+    fn warps_to_map_alone_in_doom2() {
+        assert_eq!(
+            episode_map_args(Game::Doom2, None, Some("11")),
+            ["-warp", "11"]
+        );
+        assert_eq!(
+            episode_map_args(Game::Doom2, Some("2"), Some("11")),
+            ["-warp", "11"]
+        );
+        assert_eq!(Game::Doom2.episode_map_warp(Some("2"), None), None);
+    }
+
+    #[test]
+    fn checks_iwads_by_hash() {
+        // The MD5 of the header of an IWAD with no lumps.
+        const KNOWN: &[(&str, &str, &str)] =
+            &[("2358039010418297c5ae56ea00d3c730", "doom2", "Empty Doom 2")];
+        let dir = tempfile::tempdir().unwrap();
+        let iwad = b"IWAD\0\0\0\0\0\0\0\0";
+        let pwad = b"PWAD\0\0\0\0\0\0\0\0";
+        let check = |name: &str, contents: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            check_iwad(&path, KNOWN).unwrap()
+        };
+
+        assert_eq!(check("DOOM2.WAD", iwad), IwadCheck::Release("Empty Doom 2"));
+        assert_eq!(
+            check("tnt.wad", iwad),
+            IwadCheck::OtherRelease("Empty Doom 2")
+        );
+        assert_eq!(
+            check("doom2.wad", pwad),
+            IwadCheck::Modified("b6bc51183b8639ca64f8ed11efead8ce".to_owned())
+        );
+        assert_eq!(
+            check("freedoom2.wad", pwad),
+            IwadCheck::Unknown("b6bc51183b8639ca64f8ed11efead8ce".to_owned())
+        );
+    }
+
+    #[test]
+    fn known_iwads_are_named_consistently() {
+        for (hash, stem, _) in KNOWN_IWADS {
+            assert_eq!(hash.len(), 32, "{}", hash);
+            assert!(hash
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+            assert_eq!(*stem, stem.to_lowercase());
+        }
+    }
+}
@@ -1,7 +1,49 @@
+use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
+
+use log::warn;
+
+use crate::demo::count_tics;
+use crate::demo::TICRATE;
 
 pub(crate) struct Job {
     pub name: String,
     pub demo_name: PathBuf,
     pub video_name: PathBuf,
+    /// The length of the demo in tics, if it could be read.
+    pub expected_tics: Option<u32>,
+}
+
+impl Job {
+    pub fn new(name: String, demo_name: PathBuf, video_name: PathBuf) -> Self {
+        let expected_tics = count_tics(&demo_name).unwrap_or_else(|e| {
+            warn!("{}", e);
+            None
+        });
+        Self {
+            name,
+            demo_name,
+            video_name,
+            expected_tics,
+        }
+    }
+
+    /// How long the demo runs for in game time.
+    pub fn expected_duration(&self) -> Option<Duration> {
+        self.expected_tics
+            .map(|tics| Duration::from_millis(tics as u64 * 1000 / TICRATE as u64))
+    }
+
+    /// Where to write the engine's output for this job, given the `--engine-log` file.
+    pub fn log_path(&self, log: &Path) -> PathBuf {
+        let mut file_name = log.file_stem().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(&self.name);
+        if let Some(extension) = log.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        log.with_file_name(file_name)
+    }
 }
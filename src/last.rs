@@ -0,0 +1,205 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::app;
+use crate::util::read_error;
+use crate::util::toml_error;
+use crate::util::write_error;
+use crate::Error;
+
+/// The options whose values name files or directories, by long name and short
+/// flag, and whether they take several values at a time. Their values are
+/// saved as this launch resolved them, so that `--last` doesn't depend on the
+/// directory it's run from.
+const PATH_OPTIONS: &[(&str, Option<&str>, bool)] = &[
+    ("config", None, false),
+    ("demo-dir", None, false),
+    ("engine-args-file", None, false),
+    ("engine-log", None, false),
+    ("exclude-dir", None, false),
+    ("extra-pwads", Some("x"), true),
+    ("iwad", Some("i"), false),
+    ("iwad-dir", None, false),
+    ("load-order", None, false),
+    ("mount", None, false),
+    ("pwads", Some("p"), true),
+    ("render-dir", None, false),
+    ("save-dir", None, false),
+];
+
+/// The arguments of the last successful launch, to replay with `--last`.
+#[derive(Deserialize, Serialize)]
+struct LastLaunch {
+    args: Vec<String>,
+}
+
+fn last_launch_path() -> Result<PathBuf, Error> {
+    crate::doom_dir().map(|d| d.join("last.toml"))
+}
+
+/// Remembers the arguments this program was run with for `--last`, with the
+/// values of options that name files replaced by what `resolve` returns for
+/// each option's long name and value.
+pub(crate) fn save_last_launch(
+    resolve: impl Fn(&str, &str) -> Result<String, Error>,
+) -> Result<(), Error> {
+    let args = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    write_last_launch(&last_launch_path()?, resolve_paths(&args, resolve)?)
+}
+
+fn write_last_launch(path: &Path, args: Vec<String>) -> Result<(), Error> {
+    let contents = toml::to_string(&LastLaunch { args }).map_err(Error::TomlSerialize)?;
+    std::fs::write(path, contents).map_err(write_error(path))
+}
+
+/// Replaces the values of `PATH_OPTIONS` in `args` with what `resolve` returns
+/// for them. Everything after `--` is for the engine, so it's left alone.
+fn resolve_paths(
+    args: &[String],
+    resolve: impl Fn(&str, &str) -> Result<String, Error>,
+) -> Result<Vec<String>, Error> {
+    let path_option = |arg: &str| {
+        PATH_OPTIONS.iter().find(|(long, short, _)| {
+            arg.strip_prefix("--") == Some(long)
+                || short.is_some_and(|short| arg.strip_prefix('-') == Some(short))
+        })
+    };
+    let mut resolved = Vec::with_capacity(args.len());
+    // The option the next argument is a value of, if it names files.
+    let mut option = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            resolved.push(arg.clone());
+            resolved.extend(args.cloned());
+            break;
+        }
+        if arg.starts_with('-') {
+            option = None;
+            match arg.split_once('=') {
+                Some((name, value)) => match path_option(name) {
+                    Some((long, _, _)) => {
+                        resolved.push(format!("{}={}", name, resolve(long, value)?));
+                    }
+                    None => resolved.push(arg.clone()),
+                },
+                None => {
+                    option = path_option(arg);
+                    resolved.push(arg.clone());
+                }
+            }
+        } else if let Some(&(long, _, multiple)) = option {
+            resolved.push(resolve(long, arg)?);
+            if !multiple {
+                option = None;
+            }
+        } else {
+            resolved.push(arg.clone());
+        }
+    }
+    Ok(resolved)
+}
+
+/// Parses the arguments of the last successful launch.
+pub(crate) fn read_last_launch() -> Result<ArgMatches<'static>, Error> {
+    read_last_launch_file(&last_launch_path()?)
+}
+
+fn read_last_launch_file(path: &Path) -> Result<ArgMatches<'static>, Error> {
+    if !path.exists() {
+        return Err(Error::NoLastLaunch(path.to_owned()));
+    }
+    let contents = std::fs::read(path).map_err(read_error(path))?;
+    let last: LastLaunch = toml::from_slice(&contents).map_err(toml_error(path, &contents))?;
+    app()
+        .get_matches_from_safe(std::iter::once(env!("CARGO_PKG_NAME").to_owned()).chain(last.args))
+        .map_err(|error| Error::BadLastLaunch {
+            file: path.to_owned(),
+            error,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Resolves the paths in the tests as if the Doom directory were
+    /// `/home/me/doom`, with `foo.wad` in its `pwads` directory.
+    fn resolve(option: &str, value: &str) -> Result<String, Error> {
+        Ok(match (option, value) {
+            ("pwads", "./foo.wad") => "/home/me/doom/pwads/foo.wad".to_owned(),
+            ("demo-dir", "..") => "/home/me".to_owned(),
+            _ => format!("{}={}", option, value),
+        })
+    }
+
+    #[test]
+    fn resolves_only_paths() {
+        assert_eq!(
+            resolve_paths(
+                &strings(&[
+                    "-p",
+                    "./foo.wad",
+                    "bar",
+                    "--warp",
+                    "7",
+                    "--demo-dir=..",
+                    "-x=baz",
+                    "--",
+                    "-file",
+                    "./qux.wad",
+                ]),
+                resolve
+            )
+            .unwrap(),
+            [
+                "-p",
+                "/home/me/doom/pwads/foo.wad",
+                "pwads=bar",
+                "--warp",
+                "7",
+                "--demo-dir=/home/me",
+                "-x=extra-pwads=baz",
+                "--",
+                "-file",
+                "./qux.wad",
+            ]
+        );
+        // --config takes one value; anything after it is passed through.
+        assert_eq!(
+            resolve_paths(&strings(&["--config", "a.cfg", "-fast"]), resolve).unwrap(),
+            ["--config", "config=a.cfg", "-fast"]
+        );
+    }
+
+    #[test]
+    fn saved_launch_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("last.toml");
+        assert!(matches!(
+            read_last_launch_file(&path),
+            Err(Error::NoLastLaunch(_))
+        ));
+
+        let args = strings(&["-p", "./foo.wad", "--demo-dir", "..", "--warp", "7"]);
+        write_last_launch(&path, resolve_paths(&args, resolve).unwrap()).unwrap();
+        let last = read_last_launch_file(&path).unwrap();
+        assert_eq!(
+            last.values_of("pwads").unwrap().collect::<Vec<_>>(),
+            ["/home/me/doom/pwads/foo.wad"]
+        );
+        assert_eq!(last.value_of("demo-dir"), Some("/home/me"));
+        assert_eq!(last.value_of("warp"), Some("7"));
+    }
+}
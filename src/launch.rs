@@ -0,0 +1,335 @@
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::thread::sleep;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::engine::DoomEngine;
+use crate::util::expand_env;
+use crate::util::find_in_path;
+use crate::util::write_error;
+use crate::Error;
+
+/// How engines are run under a debugger with `--debug`, unless `--debugger`
+/// or `$DOOM_DEBUGGER` says otherwise.
+pub(crate) const DEFAULT_DEBUGGER: &str = "gdb --args {binary} {args}";
+
+/// Runs an engine to completion.
+///
+/// This is what actually starts Doom, kept apart from building the command
+/// line so that something else can stand in for it, e.g. to see what would be
+/// run without having an engine installed.
+pub(crate) trait Launcher {
+    /// Runs `engine` with `argv`, whose first word is the program to run, and
+    /// waits for it to exit. If `log` is given, the engine's output goes there.
+    fn launch(
+        &self,
+        engine: &DoomEngine,
+        argv: &[&str],
+        log: Option<&Path>,
+    ) -> Result<ExitStatus, Error>;
+
+    /// Runs `engine` with `argv` like `launch`, but returns what it printed
+    /// instead of letting it through. The engine is killed if it's still
+    /// running after `timeout`, as it would be if it started a game instead.
+    fn capture(
+        &self,
+        engine: &DoomEngine,
+        argv: &[&str],
+        timeout: Duration,
+    ) -> Result<String, Error>;
+}
+
+/// Runs engines as child processes.
+pub(crate) struct ProcessLauncher;
+
+impl Launcher for ProcessLauncher {
+    fn launch(
+        &self,
+        engine: &DoomEngine,
+        argv: &[&str],
+        log: Option<&Path>,
+    ) -> Result<ExitStatus, Error> {
+        doom_command(engine, argv.iter().copied(), log)?
+            .status()
+            .map_err(Error::RunningDoom)
+    }
+
+    fn capture(
+        &self,
+        engine: &DoomEngine,
+        argv: &[&str],
+        timeout: Duration,
+    ) -> Result<String, Error> {
+        let mut child = doom_command(engine, argv.iter().copied(), None)?
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(Error::RunningDoom)?;
+        let start = Instant::now();
+        while child.try_wait().map_err(Error::RunningDoom)?.is_none() {
+            if start.elapsed() > timeout {
+                child.kill().map_err(Error::RunningDoom)?;
+                child.wait().map_err(Error::RunningDoom)?;
+                return Err(Error::EngineTimedOut(timeout.as_secs()));
+            }
+            sleep(Duration::from_millis(50));
+        }
+        let output = child.wait_with_output().map_err(Error::RunningDoom)?;
+        Ok(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+}
+
+/// Records what it's asked to run instead of running anything, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MockLauncher {
+    /// The argv of every launch and capture so far.
+    pub argvs: std::cell::RefCell<Vec<Vec<String>>>,
+    /// What every launch exits with.
+    pub status: ExitStatus,
+    /// What every capture prints.
+    pub output: String,
+}
+
+#[cfg(test)]
+impl Launcher for MockLauncher {
+    fn launch(
+        &self,
+        _engine: &DoomEngine,
+        argv: &[&str],
+        _log: Option<&Path>,
+    ) -> Result<ExitStatus, Error> {
+        self.argvs
+            .borrow_mut()
+            .push(argv.iter().map(|&word| word.to_owned()).collect());
+        Ok(self.status)
+    }
+
+    fn capture(
+        &self,
+        _engine: &DoomEngine,
+        argv: &[&str],
+        _timeout: Duration,
+    ) -> Result<String, Error> {
+        self.argvs
+            .borrow_mut()
+            .push(argv.iter().map(|&word| word.to_owned()).collect());
+        Ok(self.output.clone())
+    }
+}
+
+/// Prepares to run `engine` with the arguments in `cmdline`, from the engine's
+/// working directory.
+///
+/// If `log` is given, the engine's stdout and stderr are written to it instead
+/// of ours.
+pub(crate) fn doom_command<'l>(
+    engine: &DoomEngine,
+    mut cmdline: impl Iterator<Item = &'l str>,
+    log: Option<&Path>,
+) -> Result<Command, Error> {
+    let binary = PathBuf::from(cmdline.next().unwrap());
+    if !binary.exists() {
+        return Err(Error::FileNotFound(binary.to_string_lossy().into_owned()));
+    }
+    let mut command = Command::new(binary);
+    command.args(cmdline).current_dir(engine.working_dir());
+    for (key, value) in &engine.env {
+        command.env(key, expand_env(value));
+    }
+    if let Some(log) = log {
+        let stdout = File::create(log).map_err(write_error(log))?;
+        let stderr = stdout.try_clone().map_err(write_error(log))?;
+        command.stdout(stdout).stderr(stderr);
+    }
+    Ok(command)
+}
+
+/// How to run an engine under a debugger, parsed from a template like
+/// `rr record {binary} {args}`.
+pub(crate) struct Debugger {
+    /// The debugger and its arguments, before the engine's binary.
+    pub before_binary: Vec<String>,
+    /// Any of the debugger's arguments between the binary and the engine's
+    /// arguments, like `--` for lldb.
+    pub before_args: Vec<String>,
+}
+
+impl Debugger {
+    /// Parses `template`, whose words are separated by whitespace. `{binary}`
+    /// stands for the engine's binary and `{args}` for the engine's
+    /// arguments, which have to come last since more are added to the end of
+    /// the command line as it's built. The first word is looked for in `$PATH`
+    /// unless it's a path.
+    pub fn parse(template: &str) -> Result<Self, Error> {
+        if !cfg!(unix) {
+            return Err(Error::DebugUnsupported);
+        }
+        let bad_template = || Error::BadDebugger(template.to_owned());
+        let words = template.split_whitespace().collect::<Vec<_>>();
+        let binary = words
+            .iter()
+            .position(|&word| word == "{binary}")
+            .ok_or_else(bad_template)?;
+        if binary == 0 || words.last() != Some(&"{args}") || words[..binary].contains(&"{args}") {
+            return Err(bad_template());
+        }
+
+        let program = Path::new(words[0]);
+        let program = if program.components().count() > 1 {
+            program.to_path_buf()
+        } else {
+            find_in_path(words[0]).ok_or_else(|| Error::NoDebugger(words[0].to_owned()))?
+        };
+        Ok(Self {
+            before_binary: std::iter::once(program.to_string_lossy().into_owned())
+                .chain(words[1..binary].iter().map(|&word| word.to_owned()))
+                .collect(),
+            before_args: words[binary + 1..words.len() - 1]
+                .iter()
+                .map(|&word| word.to_owned())
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn parses_gdb_template() {
+        let debugger = Debugger::parse("/usr/bin/gdb --args {binary} {args}").unwrap();
+        assert_eq!(debugger.before_binary, ["/usr/bin/gdb", "--args"]);
+        assert!(debugger.before_args.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parses_rr_template() {
+        let debugger = Debugger::parse("/usr/bin/rr record {binary} {args}").unwrap();
+        assert_eq!(debugger.before_binary, ["/usr/bin/rr", "record"]);
+        assert!(debugger.before_args.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parses_args_after_binary() {
+        let debugger = Debugger::parse("/usr/bin/lldb {binary} -- {args}").unwrap();
+        assert_eq!(debugger.before_binary, ["/usr/bin/lldb"]);
+        assert_eq!(debugger.before_args, ["--"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_bad_templates() {
+        for template in [
+            "/usr/bin/gdb --args {args} {binary}",
+            "/usr/bin/gdb {binary} {args} --batch",
+            "/usr/bin/gdb --args {args}",
+            "{binary} {args}",
+            "",
+        ] {
+            assert!(
+                matches!(Debugger::parse(template), Err(Error::BadDebugger(_))),
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn debugger_must_be_in_path() {
+        assert!(matches!(
+            Debugger::parse("no-such-debugger-here {binary} {args}"),
+            Err(Error::NoDebugger(_))
+        ));
+    }
+
+    /// An engine as it would be read from the engines file, with `binary` in
+    /// `dir` and the rest of its table given by `extra`.
+    fn engine_in(dir: &Path, extra: &str) -> DoomEngine {
+        let binary = dir.join("engine");
+        File::create(&binary).unwrap();
+        toml::from_str(&format!(
+            "aliases = []\nbinary = {:?}\nkind = \"Boom\"\n\
+             supports_widescreen_assets = false\nrequired_args = []\n{}",
+            binary.to_string_lossy(),
+            extra
+        ))
+        .unwrap()
+    }
+
+    fn command_for(engine: &DoomEngine) -> Command {
+        let binary = engine.binary.to_string_lossy().into_owned();
+        doom_command(engine, std::iter::once(binary.as_str()), None).unwrap()
+    }
+
+    #[test]
+    fn runs_in_binary_dir_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_in(dir.path(), "");
+        assert_eq!(command_for(&engine).get_current_dir(), Some(dir.path()));
+    }
+
+    #[test]
+    fn runs_in_configured_working_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let working_dir = tempfile::tempdir().unwrap();
+        let engine = engine_in(
+            dir.path(),
+            &format!("working_dir = {:?}", working_dir.path().to_string_lossy()),
+        );
+        assert_eq!(
+            command_for(&engine).get_current_dir(),
+            Some(working_dir.path())
+        );
+    }
+
+    #[test]
+    fn missing_binary_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_in(dir.path(), "");
+        assert!(matches!(
+            doom_command(&engine, std::iter::once("/no/such/engine"), None),
+            Err(Error::FileNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn sets_engine_env_with_our_vars_expanded() {
+        std::env::set_var("PLAYDOOM_TEST_LAUNCH_HOME", "/home/doomguy");
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_in(
+            dir.path(),
+            "[env]\n\
+             SDL_AUDIODRIVER = \"pulse\"\n\
+             DOOMWADDIR = \"${PLAYDOOM_TEST_LAUNCH_HOME}/wads\"\n",
+        );
+        let command = command_for(&engine);
+        let mut envs = command
+            .get_envs()
+            .map(|(key, value)| (key.to_str().unwrap(), value.unwrap().to_str().unwrap()))
+            .collect::<Vec<_>>();
+        envs.sort();
+        assert_eq!(
+            envs,
+            [
+                ("DOOMWADDIR", "/home/doomguy/wads"),
+                ("SDL_AUDIODRIVER", "pulse")
+            ]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::borrow::Cow;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use log::LevelFilter;
+use serde::Serialize;
+
+use crate::job::Job;
+
+static JSON_LOGGING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    Human,
+    Json,
+}
+
+impl LogFormat {
+    pub const NAMES: &'static [&'static str] = &["human", "json"];
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "json" => Self::Json,
+            _ => Self::Human,
+        }
+    }
+}
+
+/// A log record or job event, as written with `--log-format json`.
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    level: &'a str,
+    timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    job: Option<JobEvent<'a>>,
+}
+
+#[derive(Serialize)]
+struct JobEvent<'a> {
+    event: &'a str,
+    name: &'a str,
+    demo: Cow<'a, str>,
+    video: Cow<'a, str>,
+    expected_tics: Option<u32>,
+}
+
+impl JsonLine<'_> {
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("log lines always serialize")
+    }
+}
+
+pub(crate) fn init_logger(level: LevelFilter, format: LogFormat) {
+    let mut builder = pretty_env_logger::formatted_builder();
+    builder.filter_level(level);
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    if format == LogFormat::Json {
+        JSON_LOGGING.store(true, Ordering::SeqCst);
+        builder.format(|buf, record| {
+            let line = JsonLine {
+                level: record.level().as_str(),
+                timestamp: buf.timestamp().to_string(),
+                target: Some(record.target()),
+                message: Some(record.args().to_string()),
+                job: None,
+            };
+            writeln!(buf, "{}", line.to_json())
+        });
+    }
+    builder.init();
+}
+
+/// Reports the progress of a rendering job as a JSON line on stderr.
+///
+/// This only does anything with `--log-format json`, and is not subject to
+/// the log level, so that supervising scripts always see job progress.
+pub(crate) fn job_event(event: &str, job: &Job) {
+    if !JSON_LOGGING.load(Ordering::SeqCst) {
+        return;
+    }
+    eprintln!("{}", job_event_line(event, job).to_json());
+}
+
+fn job_event_line<'a>(event: &'a str, job: &'a Job) -> JsonLine<'a> {
+    JsonLine {
+        level: "INFO",
+        timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        target: None,
+        message: None,
+        job: Some(JobEvent {
+            event,
+            name: &job.name,
+            demo: job.demo_name.to_string_lossy(),
+            video: job.video_name.to_string_lossy(),
+            expected_tics: job.expected_tics,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn job_events_are_json() {
+        let job = Job {
+            name: "map01 \"fast\"".to_owned(),
+            demo_name: PathBuf::from("C:\\demos\\map01.lmp"),
+            video_name: PathBuf::from("map01.mp4"),
+            expected_tics: Some(350),
+        };
+        let line = job_event_line("queued", &job).to_json();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "INFO");
+        assert_eq!(value["event"], "queued");
+        assert_eq!(value["name"], "map01 \"fast\"");
+        assert_eq!(value["demo"], "C:\\demos\\map01.lmp");
+        assert_eq!(value["video"], "map01.mp4");
+        assert_eq!(value["expected_tics"], 350);
+        assert!(value.get("message").is_none());
+    }
+}
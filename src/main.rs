@@ -1,12 +1,16 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::fs::create_dir_all;
 use std::fs::File;
 use std::io;
 use std::io::Write;
+use std::iter::FromIterator;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::exit;
 use std::process::Command;
+use std::process::ExitStatus;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::mpsc::channel;
@@ -14,37 +18,114 @@ use std::sync::mpsc::RecvError;
 use std::sync::mpsc::SendError;
 use std::thread::sleep;
 use std::time::Duration;
+use std::time::SystemTime;
 
 use clap::App;
 use clap::AppSettings;
 use clap::Arg;
+use clap::ArgGroup;
+use clap::Shell;
 use dialoguer::console::style;
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::Confirm;
 use dialoguer::Input;
 use dialoguer::MultiSelect;
+use dialoguer::Select;
 use indoc::indoc;
 use itertools::Itertools;
+use log::debug;
 use log::error;
 use log::info;
 use log::trace;
 use log::warn;
+use log::LevelFilter;
 use once_cell::sync::Lazy;
+use once_cell::sync::OnceCell;
+#[cfg(feature = "parallel-search")]
+use rayon::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
-use walkdir::WalkDir;
 
+use crate::archive::archive_entries;
+use crate::archive::is_archive;
 use crate::cmd::CommandLine;
 use crate::cmd::Line;
+use crate::dedupe::find_duplicates;
+use crate::demo::demo_format;
+use crate::demo::demo_iwads;
+use crate::demo::demos_in_dir;
+use crate::demo::desync_risks;
+use crate::demo::parse_demo_txt;
+use crate::demo::DemoFormat;
+use crate::demo::DemoMeta;
+use crate::demo_zip::extract_demo;
+use crate::demo_zip::is_demo_zip;
+use crate::engine::engines_path;
 use crate::engine::read_known_engines;
+use crate::engine::write_known_engines;
+use crate::engine::DoomEngine;
 use crate::engine::DoomEngineKind;
+use crate::engine::VIDEO_MODES;
+use crate::geometry::Geometry;
+use crate::iwad::detect_game;
+use crate::iwad::iwad_mismatch;
+use crate::iwad::verify_iwad;
+use crate::iwad::Game;
 use crate::job::Job;
+use crate::last::read_last_launch;
+use crate::last::save_last_launch;
+use crate::launch::doom_command;
+use crate::launch::Debugger;
+use crate::launch::Launcher;
+use crate::launch::ProcessLauncher;
+use crate::launch::DEFAULT_DEBUGGER;
+use crate::logging::init_logger;
+use crate::logging::job_event;
+use crate::logging::LogFormat;
+use crate::manifest::record_render;
+use crate::manifest::Manifest;
+use crate::manifest::MANIFEST_NAME;
+use crate::profile::read_profiles;
+use crate::profile::Options;
+use crate::profile::Profile;
+use crate::render::wait_for_jobs;
+use crate::render::RunningJob;
+use crate::score::score_entry;
+use crate::score::MIN_SCORE;
+use crate::search::SearchIndex;
+use crate::search::DEFAULT_SEARCH_DEPTH;
+use crate::search::SEARCH_INDEX;
+use crate::setup::setup;
 use crate::util::absolute_path;
-
+use crate::util::collect_errors;
+use crate::util::next_free_path;
+use crate::util::read_error;
+use crate::util::split_args;
+use crate::util::split_list;
+use crate::util::toml_error;
+use crate::util::write_error;
+use crate::validate::validate;
+
+mod archive;
 mod cmd;
+mod dedupe;
+mod demo;
+mod demo_zip;
 mod engine;
+mod geometry;
+mod iwad;
 mod job;
+mod last;
+mod launch;
+mod logging;
+mod manifest;
+mod profile;
+mod render;
+mod score;
+mod search;
+mod setup;
 mod util;
+mod validate;
 
 struct Pwads {
     wads: Vec<PathBuf>,
@@ -59,6 +140,11 @@ impl Pwads {
         }
     }
 
+    /// PWADs made up of `wads` alone, without any DEHACKED patches.
+    fn with_wads(wads: Vec<PathBuf>) -> Self {
+        Self { wads, dehs: vec![] }
+    }
+
     fn add_wads(&mut self, mut wads: Vec<PathBuf>) {
         self.wads.append(&mut wads);
     }
@@ -71,19 +157,199 @@ impl Pwads {
         self.dehs.append(&mut dehs);
     }
 
-    fn add_deh(&mut self, deh: PathBuf) {
-        self.dehs.push(deh);
+    /// Removes repeated wads and dehs, keeping the first occurrence of each.
+    /// Paths are compared once resolved, so that `a/../b.wad` and `b.wad` are
+    /// the same wad.
+    fn dedup(&mut self) {
+        let resolved =
+            |path: &PathBuf| std::fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        self.wads = self.wads.drain(..).unique_by(resolved).collect();
+        self.dehs = self.dehs.drain(..).unique_by(resolved).collect();
+    }
+
+    /// Sorts the wads into the order of `names`, matched against their file
+    /// names with or without the extension. Wads not named keep their order
+    /// after those that are, and names matching no wad are returned.
+    fn reorder<'a>(&mut self, names: &'a [String]) -> Vec<&'a str> {
+        let is_named = |wad: &Path, name: &str| {
+            [wad.file_name(), wad.file_stem()]
+                .iter()
+                .flatten()
+                .any(|n| n.to_string_lossy().eq_ignore_ascii_case(name))
+        };
+        // Unnamed wads sort after named ones, and the sort is stable.
+        self.wads.sort_by_key(|wad| {
+            names
+                .iter()
+                .position(|name| is_named(wad, name))
+                .map_or((1, 0), |i| (0, i))
+        });
+        names
+            .iter()
+            .map(String::as_str)
+            .filter(|name| !self.wads.iter().any(|wad| is_named(wad, name)))
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.wads.len() + self.dehs.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.wads.is_empty() && self.dehs.is_empty()
+    }
+
+    fn iter_wads(&self) -> impl Iterator<Item = &Path> {
+        self.wads.iter().map(PathBuf::as_path)
+    }
+
+    fn iter_dehs(&self) -> impl Iterator<Item = &Path> {
+        self.dehs.iter().map(PathBuf::as_path)
+    }
+
+    /// Splits the wads and dehs into groups to load in order, each made of
+    /// some wads followed by the dehs that patch the last of them. A deh
+    /// patches a wad with the same name in the same directory, as with
+    /// `foo.wad` and `foo.deh`. The dehs that don't patch any wad come last,
+    /// so with none patching a wad, this is all the wads then all the dehs.
+    fn load_order(&self) -> Vec<(Vec<&Path>, Vec<&Path>)> {
+        let mut groups = vec![];
+        let mut wads = vec![];
+        let mut patched = vec![false; self.dehs.len()];
+        for wad in &self.wads {
+            wads.push(wad.as_path());
+            let dehs = self
+                .dehs
+                .iter()
+                .enumerate()
+                .filter(|&(i, deh)| !patched[i] && patches(deh, wad))
+                .map(|(i, deh)| (i, deh.as_path()))
+                .collect::<Vec<_>>();
+            if !dehs.is_empty() {
+                for &(i, _) in &dehs {
+                    patched[i] = true;
+                }
+                groups.push((
+                    std::mem::take(&mut wads),
+                    dehs.into_iter().map(|(_, deh)| deh).collect(),
+                ));
+            }
+        }
+        let dehs = self
+            .dehs
+            .iter()
+            .zip(&patched)
+            .filter(|&(_, &patched)| !patched)
+            .map(|(deh, _)| deh.as_path())
+            .collect::<Vec<_>>();
+        if !wads.is_empty() || !dehs.is_empty() {
+            groups.push((wads, dehs));
+        }
+        groups
+    }
+}
+
+/// Adds the directories `mounts` to `pwads` if engines of `kind` can load
+/// them, or warns that they can't.
+fn add_mounts(pwads: &mut Pwads, kind: DoomEngineKind, mounts: &[&str]) -> Result<(), Error> {
+    for dir in mounts {
+        let dir = absolute_path(dir)?;
+        if !dir.is_dir() {
+            return Err(Error::NotADirectory(dir.to_string_lossy().into_owned()));
+        }
+        if kind.can_mount_dirs() {
+            pwads.add_wad(dir);
+        } else {
+            warn!(
+                "{} engines can't load directories, so '{}' won't be mounted.",
+                kind.name(),
+                dir.to_string_lossy()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Sets the console variables `cvars`, each given as `NAME=VALUE`, if engines
+/// of `kind` have them, or warns that they don't.
+fn add_cvars(cmdline: &mut CommandLine, kind: DoomEngineKind, cvars: &[&str]) -> Result<(), Error> {
+    if !kind.has_cvars() {
+        warn!(
+            "{} engines don't have console variables, so --cvar is ignored.",
+            kind.name()
+        );
+        return Ok(());
+    }
+    for cvar in cvars {
+        match cvar.split_once('=') {
+            Some((name, value)) if !name.is_empty() => cmdline.push_cvar(name, value),
+            _ => return Err(Error::BadCvar((*cvar).to_owned())),
+        }
+    }
+    Ok(())
+}
+
+/// Whether `deh` is the DEHACKED patch that goes with `wad`.
+fn patches(deh: &Path, wad: &Path) -> bool {
+    deh.parent() == wad.parent()
+        && match (deh.file_stem(), wad.file_stem()) {
+            (Some(deh), Some(wad)) => deh
+                .to_string_lossy()
+                .eq_ignore_ascii_case(&wad.to_string_lossy()),
+            _ => false,
+        }
+}
+
+/// Whether `path` is a DEHACKED patch rather than a wad, going by its extension.
+fn is_deh(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            let ext = ext.to_string_lossy();
+            ext.eq_ignore_ascii_case("deh") || ext.eq_ignore_ascii_case("bex")
+        })
+        .unwrap_or(false)
+}
+
+/// Adds each file as a wad or a DEHACKED patch, according to its extension.
+impl Extend<PathBuf> for Pwads {
+    fn extend<I: IntoIterator<Item = PathBuf>>(&mut self, files: I) {
+        for file in files {
+            if is_deh(&file) {
+                self.dehs.push(file);
+            } else {
+                self.wads.push(file);
+            }
+        }
     }
+}
 
-    fn wads(&self) -> &[PathBuf] {
-        &self.wads
+impl FromIterator<PathBuf> for Pwads {
+    fn from_iter<I: IntoIterator<Item = PathBuf>>(files: I) -> Self {
+        let mut pwads = Self::new();
+        pwads.extend(files);
+        pwads
     }
+}
+
+enum Complevel {
+    /// Let the engine pick, e.g. from the header of the demo being played.
+    Auto,
+    Level(u8),
+}
 
-    fn dehs(&self) -> &[PathBuf] {
-        &self.dehs
+impl Complevel {
+    fn parse(level: &str) -> Result<Self, Error> {
+        if level.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        match level.parse() {
+            Ok(level) if level <= 21 => Ok(Self::Level(level)),
+            _ => Err(Error::BadComplevel(level.to_owned())),
+        }
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum FileType {
     Iwad,
     Pwad,
@@ -91,13 +357,40 @@ enum FileType {
 }
 
 impl FileType {
+    const NAMES: &'static [&'static str] = &["iwad", "pwad", "demo"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "iwad" => Self::Iwad,
+            "demo" => Self::Demo,
+            _ => Self::Pwad,
+        }
+    }
+
     fn get_search_dirs(&self) -> Result<Vec<PathBuf>, Error> {
-        vec![doom_dir(), Ok(public_doom_dir())]
-            .into_iter()
-            .collect()
+        match self {
+            FileType::Demo => vec![demo_dir(), doom_dir(), Ok(public_doom_dir())],
+            FileType::Iwad => iwad_search_dirs(iwad_dirs()?, doom_dir()?)
+                .into_iter()
+                .map(Ok)
+                .collect(),
+            FileType::Pwad => pwad_search_dirs(None, doom_dir()?)
+                .into_iter()
+                .map(Ok)
+                .collect(),
+        }
+        .into_iter()
+        .collect()
     }
 }
 
+/// How long an engine gets to print its version before it's assumed to have
+/// started a game instead.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Width at which the printed command line wraps.
+const DISPLAY_WIDTH: usize = 100;
+
 #[cfg(unix)]
 const ARG_SEPARATOR: char = ':';
 #[cfg(windows)]
@@ -107,16 +400,119 @@ fn home_dir() -> Result<PathBuf, Error> {
     dirs::home_dir().ok_or(Error::Homeless)
 }
 
+/// The dedicated Doom directory: `$DOOM_DIR` if set, or `~/doom`.
 fn doom_dir() -> Result<PathBuf, Error> {
-    home_dir().map(|h| h.join("doom"))
+    match std::env::var_os("DOOM_DIR") {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => home_dir().map(|h| h.join("doom")),
+    }
+}
+
+/// The directory holding this program's own configuration (`engines.toml`,
+/// `autoloads.toml` and `profiles.toml`): `$XDG_CONFIG_HOME/doom-cli` (or the
+/// platform's equivalent) if it exists, or the Doom directory otherwise. WADs,
+/// demos and everything else the engines use stay in the Doom directory.
+fn config_dir() -> Result<PathBuf, Error> {
+    match platform_config_dir() {
+        Some(dir) if dir.is_dir() => Ok(dir),
+        _ => doom_dir(),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/doom-cli` or the platform's equivalent, whether or not it
+/// exists.
+fn platform_config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("doom-cli"))
 }
 
 fn public_doom_dir() -> PathBuf {
     PathBuf::from("/public/doom")
 }
 
+static DEMO_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// The directory demos are recorded to and searched for first. In order of
+/// precedence, this is `--demo-dir`, `$DOOM_DEMO_DIR`, or `~/doom/demo`.
 fn demo_dir() -> Result<PathBuf, Error> {
-    doom_dir().map(|d| d.join("demo"))
+    if let Some(dir) = DEMO_DIR.get() {
+        return Ok(dir.clone());
+    }
+    match std::env::var_os("DOOM_DEMO_DIR") {
+        Some(dir) => absolute_path(dir),
+        None => doom_dir().map(|d| d.join("demo")),
+    }
+}
+
+/// Where videos are kept in the Doom directory, `~/doom/video`.
+fn video_dir() -> Result<PathBuf, Error> {
+    doom_dir().map(|d| d.join("video"))
+}
+
+static IWAD_DIRS: OnceCell<Vec<PathBuf>> = OnceCell::new();
+
+/// The directories searched for IWADs before the Doom directory. These are
+/// `--iwad-dir` if given, or otherwise `~/doom/iwads`, `$DOOM_WADDIR` if set,
+/// and the system-wide `/usr/share/games/doom`.
+fn iwad_dirs() -> Result<Vec<PathBuf>, Error> {
+    if let Some(dirs) = IWAD_DIRS.get() {
+        return Ok(dirs.clone());
+    }
+    let mut dirs = vec![doom_dir()?.join("iwads")];
+    if let Some(dir) = std::env::var_os("DOOM_WADDIR") {
+        dirs.push(absolute_path(dir)?);
+    }
+    if cfg!(unix) {
+        dirs.push(PathBuf::from("/usr/share/games/doom"));
+    }
+    Ok(dirs)
+}
+
+/// The directories searched for IWADs: `iwad_dirs`, then `doom_dir` and the
+/// other places WADs are kept.
+fn iwad_search_dirs(iwad_dirs: Vec<PathBuf>, doom_dir: PathBuf) -> Vec<PathBuf> {
+    iwad_dirs
+        .into_iter()
+        .chain(vec![doom_dir, public_doom_dir()])
+        .chain(doom_wad_dirs())
+        .collect()
+}
+
+/// The directories searched for PWADs: `iwad_parent`, the directory of the
+/// IWAD being played if it's been found, then `doom_dir` and the other places
+/// WADs are kept. A PWAD made to go with a particular IWAD (as with total
+/// conversions) is often kept beside it.
+fn pwad_search_dirs(iwad_parent: Option<&Path>, doom_dir: PathBuf) -> Vec<PathBuf> {
+    iwad_parent
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(vec![doom_dir, public_doom_dir()])
+        .chain(doom_wad_dirs())
+        .collect()
+}
+
+/// The directories classic engines look for WADs in: `$DOOMWADDIR`, then
+/// each directory in `$DOOMWADPATH`, separated by the platform's path
+/// separator. These are searched last, after the Doom directory (`$DOOM_DIR`).
+fn doom_wad_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if let Some(dir) = std::env::var_os("DOOMWADDIR") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(path) = std::env::var_os("DOOMWADPATH") {
+        dirs.extend(std::env::split_paths(&path));
+    }
+    dirs.retain(|dir| !dir.as_os_str().is_empty());
+    dirs
+}
+
+/// Resolves a demo path relative to the demo directory.
+fn demo_path(demo: impl AsRef<Path>) -> Result<PathBuf, Error> {
+    let demo = demo.as_ref();
+    if demo.is_absolute() {
+        Ok(demo.to_path_buf())
+    } else {
+        demo_dir().map(|d| d.join(demo))
+    }
 }
 
 #[cfg(unix)]
@@ -140,35 +536,56 @@ static DUMP_DIR: Lazy<PathBuf> = Lazy::new(|| {
 static DUMP_DIR: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("E:").join("Videos"));
 
 fn search_files(list: &[String], ty: FileType) -> Result<Vec<PathBuf>, Error> {
-    list.iter()
-        .map(move |i| {
-            search_file_in_dirs_by(PathBuf::from(i), ty.get_search_dirs()?, |p| {
-                ["wad", "deh", "bex", "pk3", "pk7", "pke", "zip"].contains(
-                    &p.extension()
-                        .map(|ext| ext.to_string_lossy().to_string())
-                        .unwrap_or_default()
-                        .as_str(),
-                )
+    search_files_in_dirs(list, ty, &ty.get_search_dirs()?)
+}
+
+/// Like `search_files`, but in `search_dirs` instead of those for `ty`.
+fn search_files_in_dirs(
+    list: &[String],
+    ty: FileType,
+    search_dirs: &[PathBuf],
+) -> Result<Vec<PathBuf>, Error> {
+    collect_errors(
+        list.iter()
+            .map(move |i| {
+                search_file_in_dirs_by(PathBuf::from(i), ty, search_dirs.to_vec(), |p| {
+                    ["wad", "deh", "bex", "pk3", "pk7", "pke", "zip"].contains(
+                        &p.extension()
+                            .map(|ext| ext.to_string_lossy().to_string())
+                            .unwrap_or_default()
+                            .as_str(),
+                    )
+                })
             })
-        })
-        .map(|rr| rr.map(|r| r.into_iter().next().unwrap()))
-        .collect()
+            .map(|rr| rr.map(|r| r.into_iter().next().unwrap())),
+    )
 }
 
 fn search_file(name: impl AsRef<str>, ty: FileType) -> Result<Vec<PathBuf>, Error> {
-    search_file_in_dirs_by(name.as_ref().into(), ty.get_search_dirs()?, |_| true)
+    search_file_in_dirs_by(name.as_ref().into(), ty, ty.get_search_dirs()?, |_| true)
+}
+
+/// Searches for a PWAD in `search_dirs`, as made by `pwad_search_dirs`.
+fn search_pwad(name: impl AsRef<str>, search_dirs: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    search_pwad_by(name, search_dirs, |_| true)
 }
 
-fn search_file_by(
+fn search_pwad_by(
     name: impl AsRef<str>,
-    ty: FileType,
+    search_dirs: &[PathBuf],
     predicate: impl Fn(&Path) -> bool,
 ) -> Result<Vec<PathBuf>, Error> {
-    search_file_in_dirs_by(name.as_ref().into(), ty.get_search_dirs()?, predicate)
+    search_file_in_dirs_by(
+        name.as_ref().into(),
+        FileType::Pwad,
+        search_dirs.to_vec(),
+        predicate,
+    )
 }
 
 fn search_file_in_dirs_by(
     name: PathBuf,
+    ty: FileType,
     search_dirs: Vec<PathBuf>,
     predicate: impl Fn(&Path) -> bool,
 ) -> Result<Vec<PathBuf>, Error> {
@@ -180,128 +597,35 @@ fn search_file_in_dirs_by(
                 name.file_stem()
                     .ok_or_else(|| Error::NoFileStem(name.to_string_lossy().into_owned()))?,
             ),
+            ty,
             vec![parent],
             predicate,
         )
     } else {
-        for search_dir in search_dirs {
+        for search_dir in &search_dirs {
             info!(
                 "Searching for '{}' in '{}'",
                 name.to_string_lossy(),
                 search_dir.to_string_lossy()
             );
 
-            let base_name = name
-                .file_stem()
-                .ok_or_else(|| Error::NoFileStem(name.to_string_lossy().into_owned()))?;
-            let extension = name.extension();
-            let ancestors = name
-                .ancestors()
-                .skip(1)
-                .map(|p| p.to_path_buf())
-                .collect::<Vec<_>>();
-
             let search_dir = absolute_path(PathBuf::from(&search_dir))?;
-
-            // let results = WalkDir::new(search_dir)
-            //     .contents_first(true)
-            //     .into_iter()
-            //     .flat_map(|entry| entry.map_err(Error::WalkDir))
-            //     .filter(|entry| entry.path().is_dir())
-            //     .filter_map(|entry| {
-            //         entry
-            //             .path()
-            //             .file_stem()
-            //             .map(|fs| fs.to_string_lossy().eq_ignore_ascii_case(base_name))
-            //             .ok_or_else(|| Error::NoFileStem(entry.path().to_owned()))
-            //             .and_then(|stems_eq| {
-            //                 // TODO TODO TODO
-            //                 extension
-            //                     .map(|ext| ext.to_str().ok_or_else(|| Error::NonUtf8Path(ext.into())).map(|ext| ))
-            //                     .transpose()
-            //             });
-            //         None
-            //     });
-            struct SearchResult {
-                path: PathBuf,
-                score: usize,
-            }
-            let mut results = vec![];
-
-            for entry in WalkDir::new(search_dir).contents_first(true) {
-                let entry = entry?;
-
-                if entry.path().is_dir() {
-                    continue;
-                }
-
-                if !predicate(entry.path()) {
-                    continue;
-                }
-
-                let entry_extension = entry
-                    .path()
-                    .extension()
-                    .map(|e| {
-                        e.to_str().ok_or_else(|| {
-                            Error::NonUtf8Path(entry.path().to_string_lossy().into_owned())
-                        })
-                    })
-                    .transpose()?
-                    .unwrap_or("");
-
-                let mut score = 0;
-                let stem = entry.path().file_stem().ok_or_else(|| {
-                    Error::NoFileStem(entry.path().to_string_lossy().into_owned())
-                })?;
-                let stems_eq = stem
-                    .to_string_lossy()
-                    .eq_ignore_ascii_case(base_name.to_string_lossy().as_ref());
-                let stems_case_eq = stem.to_string_lossy() == base_name.to_string_lossy();
-                let extensions_match = extension
-                    .map(|ext| ext.to_string_lossy().eq_ignore_ascii_case(entry_extension))
-                    .unwrap_or(true);
-                let ancestors_eq = ancestors
-                    .iter()
-                    .zip(entry.path().ancestors().skip(1))
-                    .all_equal();
-                if stems_eq {
-                    // doom2
-                    score += 2;
-                }
-                if stems_case_eq {
-                    // DOOM2
-                    score += 5;
-                }
-                if extensions_match {
-                    // Example.wad
-                    score += 1;
-                    if stems_eq {
-                        // doom2.wad
-                        score += 10;
-                    }
-                    if stems_case_eq {
-                        score += 5;
-                    }
-                }
-                if stems_eq && ancestors_eq {
-                    // iwad/doom2
-                    score += 20;
-                }
-                if score > 1 {
-                    results.push(SearchResult {
-                        path: entry.path().into(),
-                        score,
-                    });
-                }
+            if !search_dir.exists() {
+                trace!("Skipping missing search directory");
+                continue;
             }
 
+            let results = score_dir(&name, ty, &search_dir, &predicate)?;
             if !results.is_empty() {
+                let best_score = results
+                    .iter()
+                    .map(|(_, score)| *score)
+                    .max()
+                    .unwrap_or_default();
                 let results = results
                     .into_iter()
-                    .sorted_by_key(|r| r.score)
-                    .map(|r| r.path)
-                    .rev()
+                    .filter(|(_, score)| *score == best_score)
+                    .map(|(path, _)| path)
                     .collect::<Vec<_>>();
                 trace!(
                     "Results: [{}]",
@@ -311,57 +635,269 @@ fn search_file_in_dirs_by(
                         .collect::<Vec<_>>()
                         .join(", ")
                 );
-                return Ok(results);
+                return disambiguate(&name, results);
+            }
+        }
+        if SEARCH_ARCHIVES.load(Ordering::Relaxed) {
+            if let Some(archive) = search_archives(&name, ty, &search_dirs, &predicate)? {
+                return Ok(vec![archive]);
             }
         }
         Err(Error::FileNotFound(name.to_string_lossy().into_owned()))
     }
 }
 
+/// Scores every file in `search_dir` accepted by `predicate` against `name`,
+/// returning those that match at all, in the order they were found.
+///
+/// With the `parallel-search` feature, files are scored on several threads.
+fn score_dir(
+    name: &Path,
+    ty: FileType,
+    search_dir: &Path,
+    predicate: impl Fn(&Path) -> bool,
+) -> Result<Vec<(PathBuf, usize)>, Error> {
+    let mut index = SEARCH_INDEX.lock().unwrap();
+    let candidates = index
+        .files(search_dir)?
+        .iter()
+        .filter(|entry| predicate(entry))
+        .collect::<Vec<_>>();
+    #[cfg(feature = "parallel-search")]
+    let candidates = candidates.into_par_iter();
+    #[cfg(not(feature = "parallel-search"))]
+    let candidates = candidates.into_iter();
+    candidates
+        .filter_map(|entry| match score_entry(name, entry, ty) {
+            Ok(score) if score > MIN_SCORE => Some(Ok((entry.clone(), score))),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .collect()
+}
+
+/// Like `search_file`, but returns every match in every search directory for
+/// `ty` with its score instead of choosing between them: the directories in
+/// the order they're searched, and the files in each best first.
+fn search_file_scored(name: impl AsRef<str>, ty: FileType) -> Result<Vec<(PathBuf, usize)>, Error> {
+    search_file_scored_in_dirs(Path::new(name.as_ref()), ty, &ty.get_search_dirs()?)
+}
+
+/// Like `search_file_scored`, but in `search_dirs` instead of those for `ty`.
+fn search_file_scored_in_dirs(
+    name: &Path,
+    ty: FileType,
+    search_dirs: &[PathBuf],
+) -> Result<Vec<(PathBuf, usize)>, Error> {
+    let mut results = vec![];
+    for search_dir in search_dirs {
+        let search_dir = absolute_path(search_dir)?;
+        if !search_dir.exists() {
+            continue;
+        }
+        let mut dir_results = score_dir(name, ty, &search_dir, |_| true)?;
+        dir_results.sort_by(|(a, a_score), (b, b_score)| b_score.cmp(a_score).then(a.cmp(b)));
+        results.append(&mut dir_results);
+    }
+    Ok(results)
+}
+
+/// Prints every file that matches `name` with its score. The search itself
+/// only looks as far as the first directory with a match and keeps the
+/// best-scoring files there.
+fn which(name: &str, ty: FileType) -> Result<(), Error> {
+    let results = search_file_scored(name, ty)?;
+    if results.is_empty() {
+        return Err(Error::FileNotFound(name.to_owned()));
+    }
+    for (path, score) in results {
+        println!("{:>3}  {}", score, path.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Looks for `name` inside the archives in `search_dirs`, returning the first
+/// archive that contains it. Most engines can load the archive directly.
+fn search_archives(
+    name: &Path,
+    ty: FileType,
+    search_dirs: &[PathBuf],
+    predicate: impl Fn(&Path) -> bool,
+) -> Result<Option<PathBuf>, Error> {
+    for search_dir in search_dirs {
+        let search_dir = absolute_path(search_dir)?;
+        if !search_dir.exists() {
+            continue;
+        }
+        let archives = SEARCH_INDEX
+            .lock()
+            .unwrap()
+            .files(&search_dir)?
+            .iter()
+            .filter(|f| is_archive(f) && predicate(f))
+            .cloned()
+            .collect::<Vec<_>>();
+        for archive in archives {
+            let entries = match archive_entries(&archive) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    warn!("{}", e);
+                    continue;
+                }
+            };
+            for entry in entries {
+                if score_entry(name, &entry, ty)? > MIN_SCORE {
+                    info!(
+                        "Found '{}' inside '{}'",
+                        name.to_string_lossy(),
+                        archive.to_string_lossy()
+                    );
+                    return Ok(Some(archive));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Narrows equally-scored results down so that each file name appears only
+/// once, asking the user which one they meant unless `--first-match` was given.
+/// Results with different names (e.g. `foo.wad` and `foo.deh`) are all kept.
+fn disambiguate(name: &Path, results: Vec<PathBuf>) -> Result<Vec<PathBuf>, Error> {
+    let mut groups: Vec<(String, Vec<PathBuf>)> = vec![];
+    for path in results {
+        let file_name = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match groups.iter_mut().find(|(name, _)| *name == file_name) {
+            Some((_, group)) => group.push(path),
+            None => groups.push((file_name, vec![path])),
+        }
+    }
+    let mut chosen = vec![];
+    for (_, mut group) in groups {
+        if group.len() == 1 || FIRST_MATCH.load(Ordering::Relaxed) {
+            chosen.push(group.swap_remove(0));
+        } else {
+            let index = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!(
+                    "Multiple equally good matches were found for {}. Please select one:",
+                    name.to_string_lossy()
+                ))
+                .items(
+                    &group
+                        .iter()
+                        .map(|path| path.to_string_lossy())
+                        .collect::<Vec<_>>(),
+                )
+                .default(0)
+                .interact()
+                .map_err(Error::Io)?;
+            chosen.push(group.swap_remove(index));
+        }
+    }
+    Ok(chosen)
+}
+
+/// Asks which of `options`, found by searching for `search`, to use. Each is
+/// shown with its score, to help tell apart files with similar names.
 fn select_between<P: AsRef<Path>>(
     search: impl AsRef<str>,
+    ty: FileType,
     options: impl AsRef<[P]>,
 ) -> Result<Vec<PathBuf>, Error> {
+    let items = options
+        .as_ref()
+        .iter()
+        .map(|opt| {
+            let score = score_entry(Path::new(search.as_ref()), opt.as_ref(), ty)?;
+            Ok(format!(
+                "{} (score {})",
+                opt.as_ref().to_string_lossy(),
+                score
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
     MultiSelect::new()
         .with_prompt(format!("Multiple files were found for the search term {}. Please select one or more of the following:", search.as_ref()))
-        .items(
-            &options
-                .as_ref()
-                .iter()
-                .map(|opt| opt.as_ref().to_string_lossy())
-                .collect::<Vec<_>>(),
-        )
+        .items(&items)
         .interact()
         .map(|indices| indices.iter().map(|i| options.as_ref()[*i].as_ref().to_owned()).collect())
         .map_err(Error::Io)
 }
 
-fn run_doom<'l>(mut cmdline: impl Iterator<Item = &'l str>) -> Result<(), Error> {
-    let binary = PathBuf::from(cmdline.next().unwrap());
-    if !binary.exists() {
-        return Err(Error::FileNotFound(binary.to_string_lossy().into_owned()));
-    }
-    let binary_dir = {
-        let mut d = binary.clone();
-        d.pop();
-        d
-    };
-    let args = cmdline
-        .filter_map(|arg| {
-            let trimmed = arg.trim();
-            if trimmed.is_empty() {
-                None
-            } else {
-                Some(trimmed)
-            }
+/// How Doom would be run, for other programs to read with `--emit json`.
+#[derive(Serialize)]
+struct Launch {
+    binary: String,
+    argv: Vec<String>,
+    working_dir: Option<String>,
+    /// Environment variables set (or, if null, removed) for the engine.
+    env: BTreeMap<String, Option<String>>,
+}
+
+impl Launch {
+    fn new<'l>(engine: &DoomEngine, cmdline: impl Iterator<Item = &'l str>) -> Result<Self, Error> {
+        let command = doom_command(engine, cmdline, None)?;
+        let lossy = |s: &OsStr| s.to_string_lossy().into_owned();
+        Ok(Self {
+            binary: lossy(command.get_program()),
+            argv: std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(lossy)
+                .collect(),
+            working_dir: command.get_current_dir().map(|dir| lossy(dir.as_os_str())),
+            env: command
+                .get_envs()
+                .map(|(key, value)| (lossy(key), value.map(lossy)))
+                .collect(),
         })
-        .collect::<Vec<_>>();
-    Command::new(binary)
-        .args(args)
-        .current_dir(binary_dir)
-        .status()
-        .map(|_| ())
-        .map_err(Error::RunningDoom)
+    }
+}
+
+/// Describes how an engine that didn't succeed exited, given its exit code.
+fn describe_exit(code: &Option<i32>) -> String {
+    match code {
+        Some(code) => format!("with code {}", code),
+        None => "because of a signal".to_owned(),
+    }
+}
+
+/// Records a rendering job whose engine exited with `status`.
+///
+/// A failed job is only logged, so that the rest of the batch still renders.
+/// Returns whether the job succeeded.
+fn finish_job(
+    manifest_path: &Path,
+    job: &Job,
+    engine_name: &str,
+    status: ExitStatus,
+) -> Result<bool, Error> {
+    if status.success() {
+        record_render(manifest_path, job, engine_name)?;
+        job_event("finished", job);
+    } else {
+        error!(
+            "Rendering '{}' failed: the engine exited {}",
+            job.name,
+            describe_exit(&status.code())
+        );
+        job_event("failed", job);
+    }
+    Ok(status.success())
+}
+
+/// Opens `video` with the system's default video player, if there is one.
+fn play_video(video: &Path) {
+    match open::that(video) {
+        Ok(status) if status.success() => {}
+        Ok(_) => warn!(
+            "Couldn't open '{}' with a video player.",
+            video.to_string_lossy()
+        ),
+        Err(e) => warn!("Couldn't open '{}': {}", video.to_string_lossy(), e),
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -371,8 +907,132 @@ struct Autoloads {
     iwad: HashMap<String, Vec<String>>,
 }
 
-fn autoload(pwads: &mut Pwads, engine: impl AsRef<Path>, iwad: &str) -> Result<(), Error> {
-    let autoload_path = doom_dir()?.join("autoloads.toml");
+/// Picks the IWAD that `demo` was recorded on from those that can be found,
+/// asking which one if there are several.
+fn iwad_from_demo(demo: &str) -> Result<Option<String>, Error> {
+    let demo = match search_file(demo, FileType::Demo)?.into_iter().next() {
+        Some(demo) => demo,
+        None => return Ok(None),
+    };
+    let extracted = if is_demo_zip(&demo) {
+        Some(extract_demo(&demo)?)
+    } else {
+        None
+    };
+    let candidates = demo_iwads(extracted.as_ref().map_or(&demo, |e| &e.demo))?
+        .into_iter()
+        .filter(|iwad| {
+            search_file(iwad, FileType::Iwad)
+                .map(|found| !found.is_empty())
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    let index = match candidates.len() {
+        0 => {
+            warn!(
+                "None of the IWADs '{}' could have been recorded on were found.",
+                demo.to_string_lossy()
+            );
+            return Ok(None);
+        }
+        1 => 0,
+        _ if FIRST_MATCH.load(Ordering::SeqCst) => 0,
+        _ => Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+                "Which IWAD was {} recorded on?",
+                demo.to_string_lossy()
+            ))
+            .items(&candidates)
+            .default(0)
+            .interact()
+            .map_err(Error::Io)?,
+    };
+    info!("Using {} for {}", candidates[index], demo.to_string_lossy());
+    Ok(Some(candidates[index].to_owned()))
+}
+
+/// Reads how `demo` was recorded from the text file next to it, or inside its
+/// zip, if there is one.
+fn read_demo_meta(demo: &str) -> Result<DemoMeta, Error> {
+    let demo = match search_file(demo, FileType::Demo)?.into_iter().next() {
+        Some(demo) => demo,
+        None => return Ok(DemoMeta::default()),
+    };
+    let extracted = if is_demo_zip(&demo) {
+        Some(extract_demo(&demo)?)
+    } else {
+        None
+    };
+    let text = extracted
+        .as_ref()
+        .map_or(&demo, |e| &e.demo)
+        .with_extension("txt");
+    if !text.exists() {
+        return Ok(DemoMeta::default());
+    }
+    debug!("Reading '{}'", text.to_string_lossy());
+    parse_demo_txt(&text)
+}
+
+/// The compatibility level the header of `demo` settles, if it does: only
+/// Doom 1.2 and MBF demos have a format that just one level plays back.
+fn demo_header_complevel(demo: &str) -> Result<Option<u8>, Error> {
+    let demo = match search_file(demo, FileType::Demo)?.into_iter().next() {
+        Some(demo) => demo,
+        None => return Ok(None),
+    };
+    let format = if is_demo_zip(&demo) {
+        demo_format(&extract_demo(&demo)?.demo)
+    } else {
+        demo_format(&demo)
+    };
+    Ok(format.ok().and_then(DemoFormat::complevel))
+}
+
+/// Names a demo after the level it starts on and the current time, as in
+/// `map01-20240115-2230.lmp`.
+fn auto_demo_name(game: Game, warp: &[&str]) -> String {
+    // 2024-01-15T22:30:00Z
+    let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    format!(
+        "{}-{}{}{}-{}{}.lmp",
+        game.level_name(warp),
+        &now[0..4],
+        &now[5..7],
+        &now[8..10],
+        &now[11..13],
+        &now[14..16]
+    )
+}
+
+fn autoloads_path() -> Result<PathBuf, Error> {
+    config_dir().map(|d| d.join("autoloads.toml"))
+}
+
+fn read_autoloads(autoload_path: &Path) -> Result<Autoloads, Error> {
+    let contents = std::fs::read(autoload_path).map_err(read_error(autoload_path))?;
+    toml::from_slice(&contents).map_err(toml_error(autoload_path, &contents))
+}
+
+/// Reads the PWAD names in a `--load-order` file, one per line, skipping blank
+/// lines and comments.
+fn read_load_order(file: &Path) -> Result<Vec<String>, Error> {
+    Ok(std::fs::read_to_string(file)
+        .map_err(read_error(file))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+fn autoload(
+    pwads: &mut Pwads,
+    engine: impl AsRef<Path>,
+    iwad: &str,
+    pwad_dirs: &[PathBuf],
+) -> Result<(), Error> {
+    let autoload_path = autoloads_path()?;
     File::open(&autoload_path).or_else(|e| {
         if e.kind() == std::io::ErrorKind::NotFound {
             write!(
@@ -389,117 +1049,704 @@ fn autoload(pwads: &mut Pwads, engine: impl AsRef<Path>, iwad: &str) -> Result<(
                     # Place in here those PWADs that only load under a specific sourceport. The key should be the sourceport, and the value should be the PWADs.
                     example = ["foo.wad", "bar.pk3", "baz.zip"]
                 "#},
-            ).map_err(Error::Io)?;
-            File::open(autoload_path.as_path()).map_err(Error::OpeningFile)
+            ).map_err(write_error(&autoload_path))?;
+            File::open(autoload_path.as_path()).map_err(read_error(&autoload_path))
         } else {
-            Err(Error::Io(e))
+            Err(read_error(&autoload_path)(e))
         }
     })?;
-    let autoloads: Autoloads = toml::from_slice(
-        std::fs::read(autoload_path.as_path())
-            .map_err(Error::Io)?
-            .as_slice(),
-    )
-    .map_err(|e| Error::BadToml {
-        file: autoload_path.clone(),
-        error: e,
-    })?;
-
-    let universal_pwads = search_files(&autoloads.universal, FileType::Pwad)?;
-    pwads.add_wads(universal_pwads);
+    let autoloads = read_autoloads(&autoload_path)?;
 
-    autoloads
-        .sourceport
-        .get(
-            engine
-                .as_ref()
-                .file_stem()
-                .ok_or_else(|| Error::NoFileStem(engine.as_ref().to_string_lossy().to_string()))?
-                .to_string_lossy()
-                .as_ref(),
-        )
-        .map(|engine_specific_pwads| {
-            pwads.add_wads(search_files(engine_specific_pwads, FileType::Pwad)?);
-            Result::<(), Error>::Ok(())
-        })
-        .unwrap_or(Ok(()))?;
-    if let Some(iwad_specific_pwads) = autoloads.iwad.get(iwad) {
-        pwads.add_wads(search_files(iwad_specific_pwads, FileType::Pwad)?);
-    }
+    let engine_name = engine
+        .as_ref()
+        .file_stem()
+        .ok_or_else(|| Error::NoFileStem(engine.as_ref().to_string_lossy().to_string()))?
+        .to_string_lossy();
+    let lists = std::iter::once(&autoloads.universal)
+        .chain(autoloads.sourceport.get(engine_name.as_ref()))
+        .chain(autoloads.iwad.get(iwad));
+    pwads.extend(
+        collect_errors(lists.map(|list| search_files_in_dirs(list, FileType::Pwad, pwad_dirs)))?
+            .into_iter()
+            .flatten(),
+    );
     Ok(())
 }
 
+fn print_engine_info(launcher: &dyn Launcher, name: &str, engine: &DoomEngine) {
+    let metadata = engine.binary.metadata();
+    #[cfg(unix)]
+    let executable = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata
+            .as_ref()
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    };
+    #[cfg(windows)]
+    let executable = metadata.as_ref().map(|m| m.is_file()).unwrap_or(false);
+
+    println!("Name:       {}", name);
+    if !engine.description.is_empty() {
+        println!("About:      {}", engine.description);
+    }
+    println!("Aliases:    {}", engine.aliases().join(", "));
+    println!("Binary:     {}", engine.binary.to_string_lossy());
+    println!(
+        "            {}, {}",
+        if metadata.is_ok() {
+            "exists"
+        } else {
+            "missing"
+        },
+        if executable {
+            "executable"
+        } else {
+            "not executable"
+        }
+    );
+    println!("Kind:       {}", engine.kind.name());
+    // Guessed version arguments might start a game instead, so those are left
+    // to --engine-version.
+    if engine.has_version_args() {
+        match engine_version(launcher, engine) {
+            Ok(version) => println!(
+                "Version:    {}",
+                version.lines().next().unwrap_or("unknown")
+            ),
+            Err(e) => println!("Version:    unknown ({})", e),
+        }
+    } else {
+        println!("Version:    unknown (see --engine-version)");
+    }
+    println!("Runs in:    {}", engine.working_dir().to_string_lossy());
+    println!(
+        "Widescreen: {}",
+        if engine.supports_widescreen_assets {
+            "supported"
+        } else {
+            "not supported"
+        }
+    );
+    println!("Extra args: {}", engine.required_args.join(" "));
+    for (key, value) in engine.env.iter().sorted() {
+        println!("Env:        {}={}", key, value);
+    }
+}
+
+/// Asks `engine` what version it is, returning everything it printed.
+fn engine_version(launcher: &dyn Launcher, engine: &DoomEngine) -> Result<String, Error> {
+    let binary = engine.binary.to_string_lossy();
+    let version_args = engine.version_args();
+    let argv = std::iter::once(binary.as_ref())
+        .chain(version_args.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+    let output = launcher.capture(engine, &argv, VERSION_TIMEOUT)?;
+    Ok(output.trim().to_owned())
+}
+
+/// The arguments that turn off sound or music, as asked for in `matches`.
+/// Rendering is silent by default, since it's faster.
+fn audio_args(matches: &Options, rendering: bool) -> Vec<&'static str> {
+    if matches.is_present("nosound") || (rendering && !matches.is_present("sound")) {
+        return vec!["-nosound"];
+    }
+    let mut args = vec![];
+    if matches.is_present("nomusic") {
+        args.push("-nomusic");
+    }
+    if matches.is_present("nosfx") {
+        args.push("-nosfx");
+    }
+    args
+}
+
+/// Orders `renderings` by `sort`, one of `--sort`'s keys, and then reverses
+/// them if asked to. Without a key they stay in the order given.
+fn sort_renderings(renderings: &mut [Job], sort: Option<&str>, reverse: bool) {
+    // Sorting is stable, so demos that compare equal stay in the order given.
+    match sort {
+        Some("name") => renderings.sort_by(|a, b| a.name.cmp(&b.name)),
+        Some("mtime") => renderings.sort_by_cached_key(|job| {
+            std::fs::metadata(&job.demo_name)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        }),
+        Some("size") => renderings.sort_by_cached_key(|job| {
+            std::fs::metadata(&job.demo_name)
+                .map(|metadata| metadata.len())
+                .ok()
+        }),
+        _ => {}
+    }
+    if reverse {
+        renderings.reverse();
+    }
+}
+
+/// The argument for how finely turning is recorded, for engines of `kind`
+/// that record longtics: `-longtics` when `recording` unless `short_tics`, and
+/// `-shorttics` for `short_tics` when not recording (e.g. to keep playing a
+/// demo with `--record-from-to`).
+fn tics_arg(kind: DoomEngineKind, recording: bool, short_tics: bool) -> Option<&'static str> {
+    match (recording, short_tics) {
+        _ if !kind.has_longtics() => {
+            if short_tics && !recording {
+                warn!("This engine doesn't support --short-tics; ignoring it.");
+            }
+            None
+        }
+        (true, false) => Some("-longtics"),
+        (false, true) => Some("-shorttics"),
+        _ => None,
+    }
+}
+
+/// Where `--record` records to: DEMO, or a name made by `auto_demo_name`, in
+/// `demo_dir` or a directory for its level with `--organize-demos`. Unless
+/// `--record-overwrite` is given, a demo already there is kept by recording
+/// beside it.
+fn record_path(
+    matches: &Options,
+    game: Game,
+    warp: Option<&[&str]>,
+    demo_dir: &Path,
+) -> Result<PathBuf, Error> {
+    let demo_name = match matches.value_of("record") {
+        Some(recording_demo) => PathBuf::from(recording_demo),
+        None => auto_demo_name(game, warp.unwrap_or_default()).into(),
+    };
+    let demo_path = match warp {
+        Some(warp) if matches.is_present("organize-demos") && demo_name.is_relative() => {
+            let level_dir = demo_dir.join(game.level_name(warp));
+            create_dir_all(&level_dir).map_err(write_error(&level_dir))?;
+            level_dir.join(demo_name)
+        }
+        _ => demo_dir.join(demo_name),
+    };
+    if matches.is_present("record-overwrite") {
+        return Ok(demo_path);
+    }
+    let free_path = next_free_path(&demo_path);
+    if free_path != demo_path {
+        info!(
+            "'{}' already exists; recording to '{}' instead.",
+            demo_path.to_string_lossy(),
+            free_path.to_string_lossy()
+        );
+    }
+    Ok(free_path)
+}
+
+/// Resolves `value`, given to `option`, the way this launch did so that
+/// `--last` can replay it from anywhere: the IWAD and PWADs are saved as the
+/// files that were found for them, and other paths are made absolute.
+fn resolve_for_last(
+    option: &str,
+    value: &str,
+    iwad: &str,
+    found_pwads: &HashMap<&str, Vec<PathBuf>>,
+) -> Result<String, Error> {
+    let paths = match option {
+        "iwad" => return Ok(iwad.to_owned()),
+        "pwads" | "extra-pwads" => split_list(value)
+            .into_iter()
+            .flat_map(|pwad| {
+                found_pwads
+                    .get(pwad)
+                    .cloned()
+                    .unwrap_or_else(|| vec![pwad.into()])
+            })
+            .collect(),
+        "exclude-dir" | "iwad-dir" => {
+            collect_errors(split_list(value).into_iter().map(absolute_path))?
+        }
+        _ => vec![absolute_path(value)?],
+    };
+    Ok(paths.iter().map(|path| path.to_string_lossy()).join(","))
+}
+
+/// Starts the command line that runs `engine`, under `debugger` if given, with
+/// the arguments the engine always needs.
+fn engine_cmdline(engine: &DoomEngine, debugger: Option<&Debugger>) -> Result<CommandLine, Error> {
+    let mut cmdline = CommandLine::new();
+    if let Some(debugger) = debugger {
+        cmdline.push_line(Line::from_words(&debugger.before_binary, 0));
+    }
+    cmdline.push_line(Line::from_word(
+        engine
+            .binary
+            .to_str()
+            .ok_or_else(|| Error::NonUtf8Path(engine.binary.to_string_lossy().into_owned()))?,
+        0,
+    ));
+    if let Some(debugger) = debugger.filter(|debugger| !debugger.before_args.is_empty()) {
+        cmdline.push_line(Line::from_words(&debugger.before_args, 0));
+    }
+    if !engine.required_args.is_empty() {
+        cmdline.push_line(Line::from_words(&engine.required_args, 1));
+    }
+    Ok(cmdline)
+}
+
+/// Runs `engine` with `cmdline` through `launcher`, failing if it doesn't exit
+/// successfully.
+fn play(
+    launcher: &dyn Launcher,
+    engine: &DoomEngine,
+    cmdline: &CommandLine,
+    log: Option<&Path>,
+) -> Result<(), Error> {
+    let status = launcher.launch(engine, &cmdline.iter_words().collect::<Vec<_>>(), log)?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::EngineExited {
+            code: status.code(),
+        })
+    }
+}
+
+/// The arguments for `--load-game SLOT`, where the slot is one of the ten the
+/// save menu shows.
+fn load_game_args(slot: &str) -> Result<[&str; 2], Error> {
+    match slot.parse::<u8>() {
+        Ok(0..=9) => Ok(["-loadgame", slot]),
+        _ => Err(Error::BadSaveSlot(slot.to_owned())),
+    }
+}
+
+/// The arguments for `--turbo PERCENT`, in the range engines accept.
+fn turbo_args(turbo: &str) -> Result<[&str; 2], Error> {
+    match turbo.parse::<u8>() {
+        Ok(10..=255) => Ok(["-turbo", turbo]),
+        _ => Err(Error::BadTurbo(turbo.to_owned())),
+    }
+}
+
+/// The arguments for `--timer MINUTES`, which must be at least one minute.
+fn timer_args(timer: &str) -> Result<[&str; 2], Error> {
+    match timer.parse::<u32>() {
+        Ok(minutes) if minutes > 0 => Ok(["-timer", timer]),
+        _ => Err(Error::BadTimer(timer.to_owned())),
+    }
+}
+
+/// The arguments for `--video-mode MODE` if engines of `kind` have video
+/// modes. Modes not in `VIDEO_MODES` are passed on with a warning, in case the
+/// engine knows better.
+fn video_mode_args(kind: DoomEngineKind, mode: &str) -> Option<Vec<String>> {
+    let args = kind.video_mode_args(mode)?;
+    if !VIDEO_MODES
+        .iter()
+        .any(|known| known.eq_ignore_ascii_case(mode))
+    {
+        warn!(
+            "Unknown video mode '{}' (expected one of {}); passing it on anyway.",
+            mode,
+            VIDEO_MODES.join(", ")
+        );
+    }
+    Some(args)
+}
+
+/// Pushes arguments that only some kinds of engine support, warning if the
+/// user `explicitly` asked for an `option` that the engine doesn't support.
+fn push_engine_args(
+    cmdline: &mut CommandLine,
+    args: Option<Vec<String>>,
+    explicitly: bool,
+    option: &str,
+) {
+    match args {
+        Some(args) => cmdline.push_line(Line::from_words(&args, 1)),
+        None if explicitly => warn!("This engine doesn't support --{}; ignoring it.", option),
+        None => debug!("This engine doesn't support --{}; leaving it out.", option),
+    }
+}
+
 static CANCELLABLE: AtomicBool = AtomicBool::new(false);
+static FIRST_MATCH: AtomicBool = AtomicBool::new(false);
+static SEARCH_ARCHIVES: AtomicBool = AtomicBool::new(false);
 static PAUSED: AtomicBool = AtomicBool::new(false);
 
+fn app() -> App<'static, 'static> {
+    App::new("Command-line Doom launcher")
+        .version("0.1.0")
+        .before_help("This Doom launcher allows shortcuts to the many long-winded options that Doom engines accept.")
+        .setting(AppSettings::TrailingVarArg)
+        .setting(AppSettings::ColorAuto)
+        .arg(Arg::with_name("3p").long("3p").help("Add the 3P Sound Pack"))
+        .arg(Arg::with_name("add-engine").long("add-engine").help("Add an engine to ~/doom/engines.toml").long_help("Add an engine called NAME to ~/doom/engines.toml. KIND is one of Vanilla, Boom, MBF, Eternity or ZDoom. Edit the file afterwards to set the remaining options.").number_of_values(3).value_names(&["NAME", "BINARY", "KIND"]))
+        .arg(Arg::with_name("altdeath").long("altdeath").help("Play altdeath (deathmatch 2.0)").conflicts_with("deathmatch"))
+        .arg(Arg::with_name("avg").long("avg").help("End each level after 20 minutes").conflicts_with("timer"))
+        .arg(Arg::with_name("compatibility-level").short("c").long("compatibility-level").help("Set the compatibility level to LEVEL (0-21 or 'auto')").long_help("Set the compatibility level to LEVEL, a number from 0 to 21, or 'auto' to take it from the header of the demo being played or rendered where that settles it (only for Doom 1.2 and MBF demos) and otherwise let the engine decide. Defaults to 'auto' with --play-demo or --render, and 9 otherwise. Only Boom- and MBF-derived engines (such as PrBoom+, DSDA Doom and Woof) have compatibility levels; for others, no compatibility level is passed and giving one explicitly is warned about.").value_name("LEVEL"))
+        .arg(Arg::with_name("completions").long("completions").help("Print a completion script for SHELL").possible_values(&Shell::variants()).value_name("SHELL"))
+        .arg(Arg::with_name("config").long("config").help("Use FILE as the engine's config file").long_help("Use FILE as the engine's config file, relative to ~/doom unless absolute. This is useful for keeping per-mod controls and settings.").value_name("FILE"))
+        .arg(Arg::with_name("coop-spawns").long("coop-spawns").help("Place things as in co-op").long_help("Spawn the monsters and items that only appear in co-op, while playing alone. Supported by Boom- and MBF-derived engines such as DSDA Doom and Woof; for other engines, set the arguments to use under option_args in ~/doom/engines.toml."))
+        .arg(Arg::with_name("cvar").long("cvar").help("Set the console variable NAME to VALUE").long_help("Set the console variable NAME to VALUE, given as NAME=VALUE. This can be given more than once, and is only supported by ZDoom-based engines.").value_name("NAME=VALUE").multiple(true).number_of_values(1))
+        .arg(Arg::with_name("deathmatch").long("deathmatch").help("Play deathmatch"))
+        .arg(Arg::with_name("debug").short("G").long("debug").help("Run Doom under a debugger").long_help("Run Doom under a debugger: gdb, unless --debugger or $DOOM_DEBUGGER says otherwise. Only supported on Unix."))
+        .arg(Arg::with_name("debugger").long("debugger").help("Run Doom under a debugger as TEMPLATE with --debug").long_help("Run Doom under a debugger as TEMPLATE with --debug, where {binary} is replaced with the engine's binary and {args} with its arguments, which must come last. Defaults to $DOOM_DEBUGGER if set, or 'gdb --args {binary} {args}'. For example: 'rr record {binary} {args}' or 'lldb {binary} -- {args}'.").value_name("TEMPLATE").requires("debug"))
+        .arg(Arg::with_name("dedupe-report").long("dedupe-report").help("List the WADs in the Doom directories that are identical and exit").long_help("List the WADs, PK3s and DeHackEd patches in the PWAD search directories whose contents are identical, with their sizes, and exit. Nothing is deleted unless --delete-duplicates is also given."))
+        .arg(Arg::with_name("delete-duplicates").long("delete-duplicates").help("Delete the duplicates found by --dedupe-report").long_help("Delete the duplicates found by --dedupe-report, keeping the first path of each group in alphabetical order. You are always asked to confirm first, even with --no-confirm.").requires("dedupe-report"))
+        .arg(Arg::with_name("demo-dir").long("demo-dir").help("Record and look for demos in DIR").long_help("Record and look for demos in DIR instead of ~/doom/demo. If not given, $DOOM_DEMO_DIR is used if set.").value_name("DIR"))
+        .arg(Arg::with_name("emit").long("emit").help("Print how Doom would be run in FORMAT instead of running it").long_help("Print how Doom would be run in FORMAT instead of running it, for other programs to read. 'json' prints an object with the engine's binary, the full argv (starting with the binary), the working directory and any environment variables set for the engine.").possible_values(&["json"]).value_name("FORMAT").conflicts_with("rendering"))
+        .arg(Arg::with_name("engine").short("e").long("engine").help("Play the game with ENGINE instead of the default one").long_help("Play the game with ENGINE, a name or alias from ~/doom/engines.toml, instead of the default one. The default is the engine named by the 'default' key at the top of that file, or else the first engine in that file.").value_name("ENGINE"))
+        .arg(Arg::with_name("engine-log").long("engine-log").help("Write the engine's output to FILE").long_help("Write everything the engine prints to FILE, relative to ~/doom unless absolute, instead of the terminal. When rendering with --render, each demo gets its own log next to FILE, named after FILE with the demo's name added (e.g. engine.log becomes engine.DEMO.log).").value_name("FILE"))
+        .arg(Arg::with_name("engine-args-file").long("engine-args-file").help("Pass the arguments in FILE to the engine").long_help("Pass the arguments in FILE, relative to ~/doom unless absolute, to the engine, before any given after '--'. Arguments are separated by whitespace and may be quoted with ' or \"; blank lines and lines starting with # are ignored.").value_name("FILE"))
+        .arg(Arg::with_name("engine-info").long("engine-info").help("Show everything known about ENGINE").long_help("Show everything known about ENGINE in ~/doom/engines.toml. Its version is only shown if 'version_args' is set for it, since otherwise running it might start a game; use --engine-version to ask it anyway.").value_name("ENGINE"))
+        .arg(Arg::with_name("engine-kind").long("engine-kind").help("Play the game with the first engine of KIND").long_help("Play the game with the first engine in ~/doom/engines.toml of KIND, which is one of Vanilla, Boom, MBF, Eternity or ZDoom. This lets scripts ask for, say, any Boom engine on machines whose engines are named differently.").value_name("KIND").conflicts_with("engine"))
+        .arg(Arg::with_name("engine-version").long("engine-version").help("Print the engine's version and exit").long_help("Print what the engine (the one given with --engine or --engine-kind, or the default) says its version is, and exit. The engine is run with -v for Boom and MBF engines and -version for others, unless 'version_args' is set for it in ~/doom/engines.toml."))
+        .arg(Arg::with_name("episode").long("episode").help("Start the game in episode EPISODE").long_help("Start the game in episode EPISODE, at the map given by --map or else its first map. Only Doom and Heretic have episodes.").value_name("EPISODE").conflicts_with("warp"))
+        .arg(Arg::with_name("exclude-dir").long("exclude-dir").help("Never look for files in DIR").long_help("Never look for files in DIR, relative to ~/doom unless absolute, or anything inside it. This can be given more than once, or several directories can be given separated by ',' or ':'. This can also be set in a profile.").value_name("DIR").multiple(true).number_of_values(1))
+        .arg(Arg::with_name("extra-pwads").short("x").long("extra-pwads").help("Add PWADS to the game, silently").long_help("Silently means that when rendering a demo (with --render), the program will not add these PWADs to the folder name. Like --pwads, several PWADs can be given in one value, separated by ',' or ':'.").value_name("WAD").multiple(true))
+        .arg(Arg::with_name("fast").short("f").long("fast").help("Enable fast monsters"))
+        .arg(Arg::with_name("fast-render").long("fast-render").help("Render as quickly as possible").long_help("Render as quickly as possible. With --render, this runs the engine in a window (as --windowed, unless --fullscreen is given) and without sound (as --nosound, unless --sound is given, which is already the default when rendering), and starts each demo in a batch straight away instead of pausing for 10 seconds to allow adding more demos.").requires("rendering"))
+        .arg(Arg::with_name("first-match").long("first-match").help("Pick the first of several equally good search results instead of asking"))
+        .arg(Arg::with_name("fullscreen").long("fullscreen").help("Run the game fullscreen").conflicts_with("windowed"))
+        .arg(Arg::with_name("geometry").short("g").long("geometry").help("Set the screen resolution to WxH").long_help("Set the screen resolution to WxH, optionally followed by F for fullscreen or W for windowed; only supported on Boom-derived sourceports and ZDoom.").value_name("GEOM"))
+        .arg(Arg::with_name("ignore-demo-txt").long("ignore-demo-txt").help("Don't take the IWAD, PWADs or compatibility level from the demo's text file").long_help("Don't read the text file that the demo given to --play-demo, or the first given to --render, came with. Otherwise, the IWAD, PWADs and compatibility level it names are used unless given with --iwad, --pwads or --compatibility-level."))
+        .arg(Arg::with_name("include-hidden").long("include-hidden").help("Look for files in hidden directories too").long_help("Look for files in hidden directories (those whose names start with '.', such as .git) inside the search directories too. They are skipped by default."))
+        .arg(Arg::with_name("iwad").short("i").long("iwad").help("Set the game's IWAD").value_name("WAD"))
+        .arg(Arg::with_name("iwad-from-demo").long("iwad-from-demo").help("Use the IWAD the demo was recorded on").long_help("Use the IWAD that the demo given to --play-demo, or the first given to --render, was recorded on. This is guessed from the demo's episode and map, and from a text file next to the demo with the same name if there is one. If several IWADs are possible, you are asked which one to use.").conflicts_with("iwad"))
+        .arg(Arg::with_name("iwad-dir").long("iwad-dir").help("Look for IWADs in DIR first").long_help("Look for IWADs in DIR before the Doom directory, instead of ~/doom/iwads, $DOOM_WADDIR and /usr/share/games/doom. Several directories can be given, in order of priority, separated by ',' or ':'. This can also be set in a profile.").value_name("DIR").multiple(true).number_of_values(1))
+        .arg(Arg::with_name("jobs").short("j").long("jobs").help("Render up to N demos at once").long_help("Render up to N demos at once, each in its own engine process. Defaults to 1. With more than one, every line the engines print is prefixed with the name of the demo being rendered.").value_name("N").requires("rendering"))
+        .arg(Arg::with_name("last").long("last").help("Launch the game the same way as last time").long_help("Launch the game with the same options as the last time it was launched successfully (without --last), saved in ~/doom/last.toml. Files and directories are saved as that launch found them, so --last works from any directory. Options given along with --last override the saved ones."))
+        .arg(Arg::with_name("list-profiles").long("list-profiles").help("List the launch profiles defined in ~/doom/profiles.toml"))
+        .arg(Arg::with_name("load-game").short("l").long("load-game").help("Load the saved game in SLOT (0-9)").value_name("SLOT").conflicts_with_all(&["play-demo", "record", "record-from-to", "render", "render-dir"]))
+        .arg(Arg::with_name("load-order").long("load-order").help("Load PWADs in the order listed in FILE").long_help("Load PWADs in the order listed in FILE, relative to ~/doom unless absolute, which names one PWAD per line, with or without its extension. Blank lines and lines starting with # are ignored. PWADs not listed are loaded after those that are, in their usual order.").value_name("FILE"))
+        .arg(Arg::with_name("log-format").long("log-format").help("Set the log output format").long_help("Set the log output format. 'json' writes one JSON object per line to stderr, including progress events for rendering jobs.").possible_values(LogFormat::NAMES).value_name("FORMAT"))
+        .arg(Arg::with_name("manifest").long("manifest").help("Record completed renders in FILE").long_help("Record completed renders in FILE instead of renders.json in the folder the videos are rendered to. Each entry lists the demo, the video, the engine used and when the render finished.").value_name("FILE").requires("rendering"))
+        .arg(Arg::with_name("map").long("map").help("Start the game at map MAP").long_help("Start the game at map MAP, in the episode given by --episode or else the first one for games with episodes. This works out the right -warp for the game, so --map 1 is MAP01 in Doom 2 and E1M1 in Doom.").value_name("MAP").conflicts_with("warp"))
+        .arg(Arg::with_name("mount").long("mount").help("Load DIR as if it were a PK3").long_help("Load the loose files in DIR, relative to ~/doom unless absolute, as if they were packed into a PK3. This can be given more than once, and is only supported by ZDoom-based engines.").value_name("DIR").multiple(true).number_of_values(1))
+        .arg(Arg::with_name("net").long("net").help("Join a network game").long_help("Join a network game, passing NODES to the engine's -net argument. What this means varies by engine; PrBoom+ and Chocolate Doom-style engines take the other players' addresses.").value_name("NODES").multiple(true).number_of_values(1))
+        .arg(Arg::with_name("no-confirm").long("no-confirm").help("Don't ask for confirmation before running Doom").long_help("Don't ask for confirmation before running Doom. When rendering, demos that would probably desync with the IWAD, PWADs or compatibility level being used are skipped instead of asking whether to render them anyway."))
+        .arg(Arg::with_name("no-monsters").long("no-monsters").help("Play the game with no monsters"))
+        .arg(Arg::with_name("nomusic").long("nomusic").help("Disable music"))
+        .arg(Arg::with_name("nosfx").long("nosfx").help("Disable sound effects"))
+        .arg(Arg::with_name("nosound").long("nosound").help("Disable all sound").long_help("Disable all sound. This is the default when rendering with --render; pass --sound to keep it.").conflicts_with("sound"))
+        .arg(Arg::with_name("only").long("only").help("Only render the demos in --render-dir matching GLOB").long_help("Only render the demos in --render-dir whose file names match GLOB, ignoring case. '*' matches any run of characters and '?' any one character, e.g. --only '*nomo*'.").value_name("GLOB").requires("render-dir"))
+        .arg(Arg::with_name("organize-demos").long("organize-demos").help("Record demos into a directory for each level").long_help("Record demos given to --record into a directory named after the level given to --warp, e.g. ~/doom/demo/map03/run1.lmp. The directory is created if needed. Without --warp, or with an absolute DEMO, the demo is recorded as usual.").requires("record"))
+        .arg(Arg::with_name("pistol-start").long("pistol-start").help("Play each level from a pistol start").long_help("Play each level from a pistol start. Supported by Boom- and MBF-derived engines such as PrBoom+ and DSDA Doom, and by Crispy Doom; for other engines, set the arguments to use under option_args in ~/doom/engines.toml."))
+        .arg(Arg::with_name("play-after-render").long("play-after-render").help("Watch rendered videos once they're done").long_help("Open rendered videos with the default video player once they're done. WHEN is 'each' to open every video as soon as it's rendered, or 'last' (the default) to open only the last one once the whole batch is done.").possible_values(&["each", "last"]).value_name("WHEN").min_values(0).max_values(1).requires("rendering"))
+        .arg(Arg::with_name("play-demo").short("d").long("play-demo").help("Play back DEMO").long_help("Play back DEMO, which can be a zip holding the demo and its text file, as demos are usually distributed. The demo is extracted to a temporary directory first, and if the zip holds several, you are asked which one to play.").value_name("DEMO"))
+        .arg(Arg::with_name("print-doom-dir").long("print-doom-dir").help("Print the dedicated Doom directory and exit").long_help("Print the dedicated Doom directory and exit. This is $DOOM_DIR if set, or ~/doom otherwise. The configuration files (engines.toml, autoloads.toml and profiles.toml) are read from $XDG_CONFIG_HOME/doom-cli instead if that directory exists."))
+        .arg(Arg::with_name("profile").short("P").long("profile").help("Load default options from PROFILE").long_help("Load default options from PROFILE, defined in ~/doom/profiles.toml. Options given on the command line override the profile.").value_name("PROFILE"))
+        .arg(Arg::with_name("pwads").short("p").long("pwads").help("Add PWADS to the game").long_help("Add PWADS to the game. Several PWADs can be given in one value, separated by ',' or ':' (or ';' on Windows); a Windows drive letter like 'C:' is never split.").multiple(true).value_name("WAD"))
+        .arg(Arg::with_name("quiet").short("q").long("quiet").help("Only log errors").conflicts_with("verbose"))
+        .arg(Arg::with_name("record").short("r").long("record").help("Record a demo to DEMO").value_name("DEMO").long_help("Record a demo to DEMO, relative to ~/doom/demo. Without DEMO, the demo is named after the level given to --warp and the current time (UTC), e.g. map01-20240115-2230.lmp.").min_values(0).max_values(1))
+        .arg(Arg::with_name("record-overwrite").long("record-overwrite").help("Overwrite the demo given to --record if it exists").long_help("Overwrite the demo given to --record if it already exists. Otherwise, a number is added to its name to keep the existing demo, as in run-2.lmp, run-3.lmp and so on.").requires("record"))
+        .arg(Arg::with_name("record-from-to").long("record-from-to").number_of_values(2).help("Play back FROM, allowing you to rewrite its ending to TO").long_help("Play FROM. You are allowed to press the join key at any time to begin recording your inputs from the current frame. Whenever you quit the game, the final result will be written to TO.").value_names(&["FROM", "TO"]))
+        .arg(Arg::with_name("remove-engine").long("remove-engine").help("Remove ENGINE from ~/doom/engines.toml").value_name("ENGINE"))
+        .arg(Arg::with_name("render").short("R").long("render").help("Render a demo as a video").long_help("The video will be placed in /extra/Videos/{iwad}/{pwads}/{demoname}. Several demos can be given, separated by ',' or ':' as with --pwads. A demo can be a zip holding the demo and its text file, as demos are usually distributed; it's extracted to a temporary directory first.").value_name("DEMO"))
+        .arg(Arg::with_name("respawn").long("respawn").help("Enable respawning monsters"))
+        .arg(Arg::with_name("render-dir").long("render-dir").help("Render every demo in DIR").long_help("Render every demo (.lmp file) in DIR and its subdirectories, relative to ~/doom, in order of path. Can be combined with --render, whose demos are rendered first.").value_name("DIR"))
+        .arg(Arg::with_name("resume").long("resume").help("Skip demos that have already been rendered").long_help("Skip demos that have already been rendered, according to the manifest (see --manifest). A video that exists but is missing from the manifest, e.g. because the engine crashed while rendering it, is rendered again.").requires("rendering"))
+        .arg(Arg::with_name("reverse").long("reverse").help("Render demos in reverse order").long_help("Render demos in the reverse of the order they were given in, or of the order chosen by --sort.").requires("rendering"))
+        .arg(Arg::with_name("save-dir").long("save-dir").help("Keep savegames in DIR").long_help("Keep savegames in DIR, relative to ~/doom unless absolute. The directory is created if it doesn't exist.").value_name("DIR"))
+        .arg(Arg::with_name("search-archives").long("search-archives").help("Look inside zip and pk3 files for files that can't be found otherwise").long_help("Look inside zip and pk3 files for files that can't be found otherwise, and load the archive containing them instead. This can be slow with many archives."))
+        .arg(Arg::with_name("search-depth").long("search-depth").help("Look for files at most N directories deep").long_help("Look for files at most N directories deep in each search directory. Defaults to 8. Symbolic links to directories are followed, except back into a directory they're inside of.").value_name("N"))
+        .arg(Arg::with_name("setup").long("setup").help("Set up an engine and the autoloads file interactively and exit").long_help("Set up this program interactively: add engines to ~/doom/engines.toml, choosing from the well-known engines installed or else giving the path to one, whose kind is guessed from its name, and write ~/doom/autoloads.toml with an entry for each IWAD in the IWAD directory you choose. With --no-confirm, guesses and defaults are accepted without asking."))
+        .arg(Arg::with_name("short-tics").long("short-tics").help("Play the game with short tics instead of long tics").long_help("Play the game with short tics instead of long tics. Demos recorded with --record use long tics otherwise, on Boom-, MBF- and Eternity-derived engines; other engines are left to their own default, which is short tics."))
+        .arg(Arg::with_name("skill").short("s").long("skill").help("Set the game's skill level by a number").long_help("Set the game's skill level by a number from 1 to 5. Pass 0 to leave the skill level out entirely and let the engine (or demo) decide; otherwise skill 4 is used when warping.").value_name("SKILL"))
+        .arg(Arg::with_name("solo-net").long("solo-net").help("Play alone under network game rules").long_help("Play alone under network game rules, so that things only placed in co-op appear, as practice for co-op or for categories that use them. Supported by every kind of engine except ZDoom; set the arguments to use under option_args in ~/doom/engines.toml if they're different.").conflicts_with("net"))
+        .arg(Arg::with_name("sort").long("sort").help("Render demos in order of KEY").long_help("Render demos in order of KEY: their name, their modification time (oldest first) or their size (smallest first). By default, demos are rendered in the order they were given in.").value_name("KEY").possible_values(&["name", "mtime", "size"]).requires("rendering"))
+        .arg(Arg::with_name("sound").long("sound").help("Keep sound on when rendering"))
+        .arg(Arg::with_name("timer").long("timer").help("End each level after MINUTES").value_name("MINUTES"))
+        .arg(Arg::with_name("turbo").short("t").long("turbo").help("Scale the player's speed to PERCENT (10-255)").value_name("PERCENT"))
+        .arg(Arg::with_name("type").long("type").help("Search for a file of type TYPE with --which").long_help("Search for a file of type TYPE with --which, which decides the directories searched and how matches are scored. Defaults to 'pwad'.").possible_values(FileType::NAMES).value_name("TYPE").requires("which"))
+        .arg(Arg::with_name("validate").long("validate").help("Check ~/doom/engines.toml and ~/doom/autoloads.toml and exit").long_help("Check ~/doom/engines.toml and ~/doom/autoloads.toml without running Doom: that they parse, that every engine's binary exists and that every autoloaded PWAD can be found. Every problem is reported, and the exit status is 1 if there were any."))
+        .arg(Arg::with_name("vanilla-weapons").long("vanilla-weapons").help("Load the game with smooth weapon animations"))
+        .arg(Arg::with_name("verbose").long("verbose").multiple(true).help("Log more; repeat for even more").long_help("Log more. By default only warnings and errors are shown; pass once for info, twice for debug and three times for trace. RUST_LOG, if set, takes precedence. There is no -v short form, since -v is --video-mode."))
+        .arg(Arg::with_name("verify-iwad").long("verify-iwad").help("Check the IWAD against the released versions").long_help("Check the IWAD's MD5 hash against those of the released versions of the commercial IWADs, and warn if it doesn't match the one its name says it is. A modified or corrupt IWAD makes demos desync, so this is worth doing before --render. This can also be set in a profile."))
+        .arg(Arg::with_name("video-mode").short("v").long("video-mode").help("Set the video mode of the game (software, hardware)").long_help("Set the video mode of the game: 8, 15, 16 or 32 for software rendering at that bit depth, or gl (the default) for hardware rendering. Only supported on Boom- and MBF-derived sourceports; other engines are run without it.").value_name("MODE"))
+        .arg(Arg::with_name("warp").short("w").long("warp").help("Start the game at a specific level number").long_help("Start the game at a specific level number. Episodic games (Doom and Heretic) also accept an episode and a map, either as two values or as EPISODE:MAP.").value_name("LEVEL").min_values(1).max_values(2))
+        .arg(Arg::with_name("which").long("which").help("Show where NAME would be found and exit").long_help("Show every file in the search directories that matches NAME, with the score it gets, and exit. The best-scoring files in the first directory with a match are the ones that would be loaded.").value_name("NAME"))
+        .arg(Arg::with_name("windowed").long("windowed").help("Run the game in a window"))
+        .arg(Arg::with_name("passthrough").multiple(true))
+        .group(ArgGroup::with_name("rendering").args(&["render", "render-dir"]).multiple(true))
+}
+
 fn run() -> Result<(), Error> {
-    let app = App::new("Command-line Doom launcher")
-            .version("0.1.0")
-            .before_help("This Doom launcher allows shortcuts to the many long-winded options that Doom engines accept.")
-            .setting(AppSettings::TrailingVarArg)
-            .setting(AppSettings::ColorAuto)
-            .arg(Arg::with_name("3p").long("3p").help("Add the 3P Sound Pack"))
-            .arg(Arg::with_name("compatibility-level").short("c").long("compatibility-level").help("Set the compatibility level to LEVEL").value_name("LEVEL"))
-            .arg(Arg::with_name("debug").short("G").long("debug").help("Run Doom under a debugger"))
-            .arg(Arg::with_name("engine").short("e").long("engine").help("Play the game with ENGINE instead of DSDA Doom").value_name("ENGINE"))
-            .arg(Arg::with_name("extra-pwads").short("x").long("extra-pwads").help("Add PWADS to the game, silently").long_help("Silently means that when rendering a demo (with --render), the program will not add these PWADs to the folder name.").value_name("WAD").multiple(true))
-            .arg(Arg::with_name("fast").short("f").long("fast").help("Enable fast monsters"))
-            .arg(Arg::with_name("geometry").short("g").long("geometry").help("Set the screen resolution to WxH").long_help("Set the screen resolution to WxH; only supported on Boom-derived sourceports.").value_name("GEOM"))
-            .arg(Arg::with_name("iwad").short("i").long("iwad").help("Set the game's IWAD").value_name("WAD"))
-            .arg(Arg::with_name("no-monsters").long("no-monsters").help("Play the game with no monsters"))
-            .arg(Arg::with_name("pistol-start").long("pistol-start").help("Play each level from a pistol start").long_help("Play each level from a pistol start. Currently only works with Crispy Doom and PrBoom+."))
-            .arg(Arg::with_name("play-demo").short("d").long("play-demo").help("Play back DEMO").value_name("DEMO"))
-            .arg(Arg::with_name("pwads").short("p").long("pwads").help("Add PWADS to the game").multiple(true).value_name("WAD"))
-            .arg(Arg::with_name("record").short("r").long("record").help("Record a demo to DEMO").value_name("DEMO").long_help("Record a demo to DEMO, relative to ~/doom/demo."))
-            .arg(Arg::with_name("record-from-to").long("record-from-to").number_of_values(2).help("Play back FROM, allowing you to rewrite its ending to TO").long_help("Play FROM. You are allowed to press the join key at any time to begin recording your inputs from the current frame. Whenever you quit the game, the final result will be written to TO.").value_names(&["FROM", "TO"]))
-            .arg(Arg::with_name("render").short("R").long("render").help("Render a demo as a video").long_help("The video will be placed in /extra/Videos/{iwad}/{pwads}/{demoname}.").value_name("DEMO"))
-            .arg(Arg::with_name("respawn").long("respawn").help("Enable respawning monsters"))
-            .arg(Arg::with_name("short-tics").long("short-tics").help("Play the game with short tics instead of long tics"))
-            .arg(Arg::with_name("skill").short("s").long("skill").help("Set the game's skill level by a number").value_name("SKILL"))
-            .arg(Arg::with_name("vanilla-weapons").long("vanilla-weapons").help("Load the game with smooth weapon animations"))
-            .arg(Arg::with_name("video-mode").short("v").long("video-mode").help("Set the video mode of the game (software, hardware)").long_help("Only supported on Boom-derived sourceports.").value_name("MODE"))
-            .arg(Arg::with_name("warp").short("w").long("warp").help("Start the game at a specific level number").value_name("LEVEL"))
-            .arg(Arg::with_name("passthrough").multiple(true))
-            ;
-
-    let matches = app.get_matches();
+    let matches = app().get_matches();
+
+    if let Some(shell) = matches.value_of("completions") {
+        app().gen_completions_to(
+            env!("CARGO_PKG_NAME"),
+            shell.parse::<Shell>().unwrap(),
+            &mut io::stdout(),
+        );
+        return Ok(());
+    }
+
+    init_logger(
+        if matches.is_present("quiet") {
+            LevelFilter::Error
+        } else {
+            match matches.occurrences_of("verbose") {
+                0 => LevelFilter::Warn,
+                1 => LevelFilter::Info,
+                2 => LevelFilter::Debug,
+                _ => LevelFilter::Trace,
+            }
+        },
+        LogFormat::from_name(matches.value_of("log-format").unwrap_or("human")),
+    );
+
+    if matches.is_present("print-doom-dir") {
+        println!("{}", doom_dir()?.to_string_lossy());
+        return Ok(());
+    }
 
     if !doom_dir()?.exists() {
-        let answer = Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt(format!(
-                "You don't have a dedicated Doom directory at {}. Create it?",
-                doom_dir()?.to_string_lossy()
-            ))
-            .interact()
-            .map_err(Error::Io)?;
+        let answer = matches.is_present("no-confirm")
+            || Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!(
+                    "You don't have a dedicated Doom directory at {}. Create it?",
+                    doom_dir()?.to_string_lossy()
+                ))
+                .interact()
+                .map_err(Error::Io)?;
         if answer {
-            create_dir_all(doom_dir()?).map_err(Error::Io)?;
+            let dirs = vec![doom_dir()?, demo_dir()?, video_dir()?]
+                .into_iter()
+                .chain(platform_config_dir());
+            for dir in dirs {
+                create_dir_all(&dir).map_err(write_error(&dir))?;
+            }
             info!("Success.");
         } else {
-            warn!("Cannot continue. You can set the dedicated Doom directory by passing the flag --doom-dir. You only have to pass the flag once, as it will be remembered.");
+            warn!("Cannot continue. You can set the dedicated Doom directory with the DOOM_DIR environment variable.");
             return Ok(());
         }
     }
 
-    let known_engines = read_known_engines()?;
-    let engine_name = matches
-        .value_of("engine")
-        .map(|s| s.to_owned())
-        .or_else(|| known_engines.iter().next())
-        .ok_or(Error::NoEngines)?;
+    if matches.is_present("setup") {
+        return setup(matches.is_present("no-confirm"));
+    }
+
+    if matches.is_present("validate") {
+        FIRST_MATCH.store(true, Ordering::SeqCst);
+        let problems = validate()?;
+        for problem in &problems {
+            error!("{}", problem);
+        }
+        match problems.len() {
+            0 => println!("No problems found."),
+            1 => println!("Found 1 problem."),
+            n => println!("Found {} problems.", n),
+        }
+        exit(if problems.is_empty() { 0 } else { 1 });
+    }
+
+    if matches.is_present("dedupe-report") {
+        let dirs = FileType::Pwad
+            .get_search_dirs()?
+            .into_iter()
+            .map(absolute_path)
+            .collect::<Result<Vec<_>, _>>()?;
+        let groups = find_duplicates(&dirs)?;
+        for group in &groups {
+            println!("{} bytes:", group.size);
+            for path in &group.paths {
+                println!("    {}", path.to_string_lossy());
+            }
+        }
+        match groups.len() {
+            0 => println!("No duplicates found."),
+            1 => println!("Found 1 group of duplicates."),
+            n => println!("Found {} groups of duplicates.", n),
+        }
+
+        let duplicates = groups
+            .iter()
+            .flat_map(|group| group.paths.iter().skip(1))
+            .collect::<Vec<_>>();
+        if matches.is_present("delete-duplicates")
+            && !duplicates.is_empty()
+            // Deleting can't be undone, so this is asked even with --no-confirm.
+            && Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!(
+                    "Delete {} files, keeping the first of each group?",
+                    duplicates.len()
+                ))
+                .interact()
+                .map_err(Error::Io)?
+        {
+            for path in duplicates {
+                info!("Deleting '{}'", path.to_string_lossy());
+                std::fs::remove_file(path).map_err(write_error(path))?;
+            }
+        }
+        return Ok(());
+    }
+
+    if matches.is_present("list-profiles") {
+        read_profiles()?
+            .keys()
+            .sorted()
+            .for_each(|name| println!("{}", name));
+        return Ok(());
+    }
+
+    let last = if matches.is_present("last") {
+        Some(read_last_launch()?)
+    } else {
+        None
+    };
+    let profile = match matches
+        .value_of("profile")
+        .or_else(|| last.as_ref().and_then(|last| last.value_of("profile")))
+    {
+        Some(name) => read_profiles()?
+            .remove(name)
+            .ok_or_else(|| Error::UnknownProfile(name.to_owned()))?,
+        None => Profile::default(),
+    };
+    let matches = Options::new(matches, last, profile);
+    let rendering = matches.is_present("render") || matches.is_present("render-dir");
+
+    if let Some(dir) = matches.value_of("demo-dir") {
+        DEMO_DIR.set(absolute_path(dir)?).unwrap();
+    }
+    if let Some(dirs) = matches.values_of("iwad-dir") {
+        let dirs = dirs
+            .into_iter()
+            .flat_map(split_list)
+            .map(absolute_path)
+            .collect::<Result<_, _>>()?;
+        IWAD_DIRS.set(dirs).unwrap();
+    }
+    let excluded_dirs = match matches.values_of("exclude-dir") {
+        Some(dirs) => dirs
+            .into_iter()
+            .flat_map(split_list)
+            .map(absolute_path)
+            .collect::<Result<_, _>>()?,
+        None => vec![],
+    };
+    let search_depth = match matches.value_of("search-depth") {
+        Some(depth) => match depth.parse::<usize>() {
+            Ok(depth) if depth > 0 => depth,
+            _ => return Err(Error::BadSearchDepth(depth.to_owned())),
+        },
+        None => DEFAULT_SEARCH_DEPTH,
+    };
+    *SEARCH_INDEX.lock().unwrap() = SearchIndex::new(
+        search_depth,
+        matches.is_present("include-hidden"),
+        excluded_dirs,
+    );
+    FIRST_MATCH.store(matches.is_present("first-match"), Ordering::SeqCst);
+    SEARCH_ARCHIVES.store(matches.is_present("search-archives"), Ordering::SeqCst);
+
+    if let Some(name) = matches.value_of("which") {
+        let ty = FileType::from_name(matches.value_of("type").unwrap_or("pwad"));
+        return which(name, ty);
+    }
+
+    let mut known_engines = read_known_engines()?;
+    if let Some(name) = matches.value_of("remove-engine") {
+        let (name, _) = known_engines
+            .remove(name)
+            .ok_or_else(|| Error::UnknownEngine(name.to_owned()))?;
+        write_known_engines(&known_engines, &engines_path()?)?;
+        info!("Removed engine '{}'.", name);
+        return Ok(());
+    }
+    if let Some(name) = matches.value_of("engine-info") {
+        let (canonical_name, engine) = known_engines.get_named(name).unwrap_or_else(|| {
+            error!("ERROR: Unknown sourceport '{}'", name);
+            if let Some(suggestion) = known_engines.suggest(name) {
+                error!("Did you mean '{}'?", suggestion);
+            }
+            exit(-1);
+        });
+        print_engine_info(&ProcessLauncher, canonical_name, engine);
+        return Ok(());
+    }
+    if let Some(new_engine) = matches.values_of("add-engine") {
+        let (name, binary, kind) = (new_engine[0], new_engine[1], new_engine[2]);
+        let kind = DoomEngineKind::from_name(kind)
+            .ok_or_else(|| Error::UnknownEngineKind(kind.to_owned()))?;
+        known_engines.insert(
+            name.to_owned(),
+            DoomEngine::new(name, absolute_path(binary)?, kind),
+        )?;
+        write_known_engines(&known_engines, &engines_path()?)?;
+        info!("Added engine '{}'.", name);
+        return Ok(());
+    }
+    let engine_name = match matches.value_of("engine-kind") {
+        Some(kind) => {
+            let kind = DoomEngineKind::from_name(kind)
+                .ok_or_else(|| Error::UnknownEngineKind(kind.to_owned()))?;
+            known_engines
+                .first_of_kind(kind)
+                .ok_or(Error::NoEngineOfKind(kind.name()))?
+                .to_owned()
+        }
+        None => matches
+            .value_of("engine")
+            .map(|s| s.to_owned())
+            .or_else(|| known_engines.default_name().map(|s| s.to_owned()))
+            .ok_or(Error::NoEngines)?,
+    };
     let engine = &known_engines.get(&engine_name).unwrap_or_else(|| {
         error!("ERROR: Unknown sourceport '{}'", engine_name);
+        if let Some(suggestion) = known_engines.suggest(&engine_name) {
+            error!("Did you mean '{}'?", suggestion);
+        }
         exit(-1);
     });
+    if matches.is_present("engine-version") {
+        println!("{}", engine_version(&ProcessLauncher, engine)?);
+        return Ok(());
+    }
 
-    let mut search_iwads: Box<dyn Iterator<Item = String>> = matches
-        .value_of("iwad")
-        .map::<Box<dyn Iterator<Item = String>>, _>(|i| Box::new(std::iter::once(i.to_string())))
+    // The demo being played, or the first being rendered.
+    let first_demo = matches.value_of("play-demo").or_else(|| {
+        matches
+            .value_of("render")
+            .and_then(|r| split_list(r).first().copied())
+    });
+    let demo_meta = match first_demo {
+        Some(demo) if !matches.is_present("ignore-demo-txt") => read_demo_meta(demo)?,
+        _ => DemoMeta::default(),
+    };
+    let demo_iwad = if matches.is_present("iwad-from-demo") {
+        match first_demo {
+            Some(demo) => iwad_from_demo(demo)?,
+            None => {
+                warn!("--iwad-from-demo needs a demo to play or render.");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut search_iwads: Box<dyn Iterator<Item = String>> = demo_iwad
+        .or_else(|| matches.value_of("iwad").map(|i| i.to_owned()))
+        .or_else(|| {
+            let iwad = demo_meta.iwad.clone()?;
+            info!("Using {}, from the demo's text file", iwad);
+            Some(iwad)
+        })
+        .map::<Box<dyn Iterator<Item = String>>, _>(|i| Box::new(std::iter::once(i)))
         .unwrap_or_else(|| {
             Box::new(
-                ["DOOM2.WAD", "DOOM.WAD", "DOOMU.WAD", "DOOM1.WAD"]
-                    .iter()
-                    .map(|i: &&str| i.to_string()),
+                [
+                    "DOOM2.WAD",
+                    "DOOM.WAD",
+                    "DOOMU.WAD",
+                    "DOOM1.WAD",
+                    "HERETIC.WAD",
+                    "HEXEN.WAD",
+                    "STRIFE1.WAD",
+                ]
+                .iter()
+                .map(|i: &&str| i.to_string()),
             )
         });
     let iwad_path = loop {
@@ -527,6 +1774,10 @@ fn run() -> Result<(), Error> {
     let iwad_path = iwad_path.unwrap();
     let iwad_path = absolute_path(&iwad_path[0])?;
     let iwad = iwad_path.to_string_lossy().to_string();
+    let pwad_dirs = pwad_search_dirs(iwad_path.parent(), doom_dir()?);
+    if matches.is_present("verify-iwad") {
+        verify_iwad(&iwad_path)?;
+    }
 
     let iwad_base = iwad_path
         .file_name()
@@ -544,69 +1795,80 @@ fn run() -> Result<(), Error> {
         })?
         .to_lowercase();
 
-    let mut cmdline = CommandLine::new();
-    if matches.is_present("debug") {
-        cmdline.push_line(Line::from_word("/usr/bin/lldb", 0));
-    }
-    cmdline.push_line(Line::from_word(
-        engine
-            .binary
-            .to_str()
-            .ok_or_else(|| Error::NonUtf8Path(engine.binary.to_string_lossy().into_owned()))?,
-        0,
-    ));
-    if matches.is_present("debug") {
-        cmdline.push_line(Line::from_word("--", 0));
-    }
-    if !engine.required_args.is_empty() {
-        cmdline.push_line(Line::from_words(&engine.required_args, 1));
-    }
-    cmdline.push_line(Line::from_words(&["-iwad", &iwad], 1));
+    let game = detect_game(&iwad_path);
+
+    let debugger = if matches.is_present("debug") {
+        let template = match matches.value_of("debugger") {
+            Some(template) => template.to_owned(),
+            None => std::env::var("DOOM_DEBUGGER").unwrap_or_else(|_| DEFAULT_DEBUGGER.to_owned()),
+        };
+        Some(Debugger::parse(&template)?)
+    } else {
+        None
+    };
 
-    let mut pwads = Pwads::new();
+    let mut cmdline = engine_cmdline(engine, debugger.as_ref())?;
+    cmdline.push_line(Line::from_words(&["-iwad", &iwad], 1));
 
-    if engine.supports_widescreen_assets {
-        if let Ok(assets) = search_file(
-            format!("{}_widescreen_assets.wad", iwad_noext),
-            FileType::Pwad,
-        ) {
-            pwads.add_wads(assets);
-        } else {
-            warn!(
-                "Couldn't find widescreen assets for {}.",
-                match iwad_noext.as_str() {
-                    "doom" => "Doom",
-                    "doom2" => "Doom 2",
-                    "tnt" => "TNT: Evilution",
-                    "plutonia" => "The Plutonia Experiment",
-                    _ => "<unknown IWAD>",
-                }
-            );
+    let mut pwads = if engine.supports_widescreen_assets && game.is_doom() {
+        match search_pwad(format!("{}_widescreen_assets.wad", iwad_noext), &pwad_dirs) {
+            Ok(assets) => Pwads::with_wads(assets),
+            Err(_) => {
+                warn!(
+                    "Couldn't find widescreen assets for {}.",
+                    match iwad_noext.as_str() {
+                        "doom" => "Doom",
+                        "doom2" => "Doom 2",
+                        "tnt" => "TNT: Evilution",
+                        "plutonia" => "The Plutonia Experiment",
+                        _ => "<unknown IWAD>",
+                    }
+                );
+                Pwads::new()
+            }
         }
-    }
+    } else {
+        Pwads::new()
+    };
 
     let (sprite_fix, deh_fix) = match iwad_noext.as_str() {
         "doom2" | "tnt" | "plutonia" => (
-            search_file("d2spfx19.wad", FileType::Pwad)?,
-            search_file("d2dehfix.deh", FileType::Pwad)?,
+            search_pwad("d2spfx19.wad", &pwad_dirs)?,
+            search_pwad("d2dehfix.deh", &pwad_dirs)?,
         ),
         "doom" => (
-            search_file("d1spfx19.wad", FileType::Pwad)?,
-            search_file("d1dehfix.deh", FileType::Pwad)?,
+            search_pwad("d1spfx19.wad", &pwad_dirs)?,
+            search_pwad("d1dehfix.deh", &pwad_dirs)?,
         ),
         _ => (vec![], vec![]),
     };
     pwads.add_wads(sprite_fix);
     pwads.add_dehs(deh_fix);
 
-    autoload(&mut pwads, &engine.binary, &iwad_noext)?;
+    autoload(&mut pwads, &engine.binary, &iwad_noext, &pwad_dirs)?;
 
     let mut viddump_folder_name = vec![];
-
-    if let Some(arg_pwads_raw) = matches.value_of("pwads") {
+    // The files each PWAD given on the command line was found as, to save
+    // for --last.
+    let mut found_pwads = HashMap::new();
+
+    let arg_pwads_raw = matches
+        .values_of("pwads")
+        .map(|raw| raw.into_iter().flat_map(split_list).collect::<Vec<_>>())
+        .or_else(|| {
+            if demo_meta.pwads.is_empty() {
+                return None;
+            }
+            info!(
+                "Loading {}, from the demo's text file",
+                demo_meta.pwads.join(", ")
+            );
+            Some(demo_meta.pwads.iter().map(|pwad| pwad.as_str()).collect())
+        });
+    if let Some(arg_pwads_raw) = arg_pwads_raw {
         let mut arg_pwads = vec![];
-        for pwad in arg_pwads_raw.split(ARG_SEPARATOR) {
-            let mut pwad_files = search_file_by(pwad, FileType::Pwad, |f| {
+        for pwad in arg_pwads_raw {
+            let mut pwad_files = search_pwad_by(pwad, &pwad_dirs, |f| {
                 f.extension()
                     .and_then(|ext| ext.to_str())
                     .map(|ext| {
@@ -615,8 +1877,9 @@ fn run() -> Result<(), Error> {
                     })
                     .unwrap_or(false)
             })?;
+            found_pwads.insert(pwad, pwad_files.clone());
             viddump_folder_name.extend(
-                search_file(pwad, FileType::Pwad)?
+                search_pwad(pwad, &pwad_dirs)?
                     .iter()
                     .map(|p| {
                         p.file_stem()
@@ -632,154 +1895,339 @@ fn run() -> Result<(), Error> {
             );
             arg_pwads.append(&mut pwad_files);
         }
-        for pwad in arg_pwads {
-            match pwad
-                .extension()
-                .ok_or_else(|| Error::NoFileExtension(pwad.to_string_lossy().into_owned()))
-                .and_then(|ext| {
-                    ext.to_str()
-                        .ok_or_else(|| Error::NonUtf8Path(ext.to_string_lossy().into_owned()))
-                })?
-                .to_lowercase()
-                .as_str()
-            {
-                "wad" | "pk3" | "zip" | "pk7" | "pke" => pwads.add_wad(pwad),
-                "deh" | "bex" => pwads.add_deh(pwad),
-                _ => unreachable!(),
-            }
-        }
+        pwads.extend(arg_pwads);
     }
 
-    if let Some(extra_pwads) = matches.value_of("extra-pwads") {
-        for pwad in extra_pwads.split(':') {
-            pwads.add_wads(search_file(pwad, FileType::Pwad)?);
+    if let Some(extra_pwads) = matches.values_of("extra-pwads") {
+        for pwad in extra_pwads.into_iter().flat_map(split_list) {
+            let pwad_files = search_pwad(pwad, &pwad_dirs)?;
+            found_pwads.insert(pwad, pwad_files.clone());
+            pwads.add_wads(pwad_files);
         }
     }
 
+    if let Some(cvars) = matches.values_of("cvar") {
+        add_cvars(&mut cmdline, engine.kind, &cvars)?;
+    }
+
+    if let Some(mounts) = matches.values_of("mount") {
+        add_mounts(&mut pwads, engine.kind, &mounts)?;
+    }
+
     if matches.is_present("vanilla-weapons") {
-        pwads.add_wads(search_file("vsmooth.wad", FileType::Pwad)?);
-        pwads.add_dehs(search_file("vsmooth.deh", FileType::Pwad)?);
+        pwads.add_wads(search_pwad("vsmooth.wad", &pwad_dirs)?);
+        pwads.add_dehs(search_pwad("vsmooth.deh", &pwad_dirs)?);
     }
 
     if matches.is_present("3p") {
-        let sound_pack = search_file("3P Sound Pack.wad", FileType::Pwad)?;
+        let sound_pack = search_pwad("3P Sound Pack.wad", &pwad_dirs)?;
         pwads.add_wad(&sound_pack[0]);
     }
 
-    if !pwads.wads().is_empty() {
-        cmdline.push_line(Line::from_word("-file", 1));
-        pwads.wads().iter().try_for_each(|pwad| {
-            pwad.to_str()
-                .ok_or_else(|| Error::NonUtf8Path(pwad.to_string_lossy().into_owned()))
-                .map(|pwad| cmdline.push_line(Line::from_word(pwad, 2)))
-        })?;
+    pwads.dedup();
+    if let Some(file) = matches.value_of("load-order") {
+        let file = absolute_path(file)?;
+        let names = read_load_order(&file)?;
+        for name in pwads.reorder(&names) {
+            warn!(
+                "'{}' in '{}' isn't being loaded.",
+                name,
+                file.to_string_lossy()
+            );
+        }
+    }
+    if !pwads.is_empty() {
+        info!("Loading {} PWADs", pwads.len());
     }
 
-    if !pwads.dehs().is_empty() {
-        cmdline.push_line(Line::from_word("-deh", 1));
-        pwads.dehs().iter().try_for_each(|deh| {
-            deh.to_str()
-                .ok_or_else(|| Error::NonUtf8Path(deh.to_string_lossy().into_owned()))
-                .map(|deh| cmdline.push_line(Line::from_word(deh, 2)))
-        })?;
+    for pwad in pwads.iter_wads() {
+        if let Some(game) = iwad_mismatch(pwad, &iwad_noext) {
+            warn!(
+                "'{}' is made for {}, but the IWAD is {}. It probably won't work.",
+                pwad.to_string_lossy(),
+                game,
+                iwad_base
+            );
+        }
     }
 
-    let complevel = matches.value_of("compatibility-level").unwrap_or("9");
-    cmdline.push_line(Line::from_words(
-        &[String::from("-complevel"), complevel.to_string()],
-        1,
-    ));
+    for (wads, dehs) in pwads.load_order() {
+        for (arg, files) in [("-file", wads), ("-deh", dehs)].iter() {
+            if files.is_empty() {
+                continue;
+            }
+            let mut words = vec![*arg];
+            for file in files {
+                words.push(
+                    file.to_str()
+                        .ok_or_else(|| Error::NonUtf8Path(file.to_string_lossy().into_owned()))?,
+                );
+            }
+            cmdline.push_line(Line::from_words(&words, 1));
+        }
+    }
 
-    if matches.is_present("pistol-start") {
-        cmdline.push_line(Line::from_word("-pistolstart", 1));
+    if let Some(config) = matches.value_of("config") {
+        let config = absolute_path(config)?;
+        if !config.exists() {
+            warn!(
+                "Config file '{}' doesn't exist; the engine may create it.",
+                config.to_string_lossy()
+            );
+        }
+        cmdline.push_line(Line::from_words(&["-config", &config.to_string_lossy()], 1));
+    }
+
+    let complevel = match (matches.value_of("compatibility-level"), demo_meta.complevel) {
+        (Some(level), _) => Complevel::parse(level)?,
+        (None, Some(level)) => {
+            info!(
+                "Using compatibility level {}, from the demo's text file",
+                level
+            );
+            Complevel::Level(level)
+        }
+        (None, None)
+            if matches.is_present("play-demo")
+                || matches.is_present("record-from-to")
+                || rendering =>
+        {
+            match first_demo.map(demo_header_complevel).transpose()?.flatten() {
+                Some(level) => {
+                    info!(
+                        "Using compatibility level {}, from the demo's header",
+                        level
+                    );
+                    Complevel::Level(level)
+                }
+                None => Complevel::Auto,
+            }
+        }
+        (None, None) => Complevel::Level(9),
+    };
+    if let Complevel::Level(level) = complevel {
+        push_engine_args(
+            &mut cmdline,
+            engine.kind.complevel_args(level),
+            matches.value_of("compatibility-level").is_some(),
+            "compatibility-level",
+        );
     }
 
-    let vidmode = matches.value_of("video-mode").unwrap_or("GL");
-    cmdline.push_line(Line::from_words(&["-vidmode", vidmode], 1));
+    if matches.is_present("coop-spawns") {
+        push_engine_args(&mut cmdline, engine.coop_spawns_args(), true, "coop-spawns");
+    }
 
-    let geom = matches.value_of("geometry").unwrap_or("2560x1440F");
-    cmdline.push_line(Line::from_words(&["-geom", geom], 1));
+    if matches.is_present("solo-net") {
+        push_engine_args(&mut cmdline, engine.solo_net_args(), true, "solo-net");
+    }
 
-    let skill_param = if engine.kind == DoomEngineKind::ZDoom {
-        &["+skill", "3"]
+    if matches.is_present("pistol-start") {
+        push_engine_args(
+            &mut cmdline,
+            engine.pistol_start_args(),
+            true,
+            "pistol-start",
+        );
+    }
+
+    push_engine_args(
+        &mut cmdline,
+        video_mode_args(engine.kind, matches.value_of("video-mode").unwrap_or("GL")),
+        matches.value_of("video-mode").is_some(),
+        "video-mode",
+    );
+
+    let fast_render = matches.is_present("fast-render") && rendering;
+    let fullscreen = if matches.is_present("fullscreen") {
+        Some(true)
+    } else if matches.is_present("windowed") || fast_render {
+        Some(false)
     } else {
-        &["-skill", "4"]
+        None
     };
+    if let Some(fullscreen) = fullscreen {
+        push_engine_args(
+            &mut cmdline,
+            engine.kind.fullscreen_args(fullscreen),
+            true,
+            if fullscreen { "fullscreen" } else { "windowed" },
+        );
+    }
 
-    if let Some(recording_demo) = matches.value_of("record") {
-        let demo_path = PathBuf::from(recording_demo);
-        let demo_path = if demo_path.is_absolute() {
-            demo_path
-        } else {
-            demo_dir()?.join(demo_path)
-        };
+    let mut geom = Geometry::parse(matches.value_of("geometry").unwrap_or("2560x1440F"))?;
+    if fullscreen.is_some() {
+        geom.fullscreen = fullscreen;
+    }
+    push_engine_args(
+        &mut cmdline,
+        engine.kind.geometry_args(geom),
+        matches.value_of("geometry").is_some(),
+        "geometry",
+    );
+
+    let warp = match matches.values_of("warp") {
+        Some(warp) => Some(
+            warp.into_iter()
+                .flat_map(|w| w.split(':'))
+                .collect::<Vec<_>>(),
+        ),
+        None => game.episode_map_warp(matches.value_of("episode"), matches.value_of("map")),
+    };
+
+    if matches.is_present("record") {
+        let demo_path = record_path(&matches, game, warp.as_deref(), &demo_dir()?)?;
         cmdline.push_line(Line::from_word("-record", 1));
         cmdline.push_line(Line::from_word(demo_path.to_string_lossy(), 2));
-        if !matches.is_present("short-tics") {
-            cmdline.push_line(Line::from_word("-longtics", 1));
-        }
-    } else if matches.is_present("short-tics") {
-        cmdline.push_line(Line::from_word("-shorttics", 1));
+    }
+    if let Some(arg) = tics_arg(
+        engine.kind,
+        matches.is_present("record"),
+        matches.is_present("short-tics"),
+    ) {
+        cmdline.push_line(Line::from_word(arg, 1));
     }
 
     if let Some(from_to) = matches.values_of("record-from-to") {
-        let from_to = from_to.collect::<Vec<_>>();
         cmdline.push_line(Line::from_word("-recordfromto", 1));
-        cmdline.push_line(Line::from_words(&from_to[0..2], 2));
+        let from = search_file(from_to[0], FileType::Demo)?;
+        if from.is_empty() {
+            return Err(Error::FileNotFound(from_to[0].to_owned()));
+        }
+        let format = demo_format(&from[0])?;
+        if !engine.kind.can_continue(format) {
+            return Err(Error::CannotContinueDemo {
+                demo: from[0].to_string_lossy().into_owned(),
+                format,
+                engine: engine_name.clone(),
+            });
+        }
+        if let Complevel::Level(level) = complevel {
+            if !format.complevels().contains(&level) {
+                return Err(Error::ComplevelMismatch {
+                    demo: from[0].to_string_lossy().into_owned(),
+                    format,
+                    level,
+                });
+            }
+        }
+        let to = demo_path(from_to[1])?;
+        cmdline.push_line(Line::from_words(
+            &[from[0].to_string_lossy(), to.to_string_lossy()],
+            2,
+        ));
     }
 
+    // Demos extracted from zips, removed once they've been played or rendered.
+    let mut extracted_demos = vec![];
     if let Some(playing_demo) = matches.value_of("play-demo") {
-        let demo = select_between(playing_demo, search_file(playing_demo, FileType::Demo)?)?;
+        let demo = select_between(
+            playing_demo,
+            FileType::Demo,
+            search_file(playing_demo, FileType::Demo)?,
+        )?;
         if demo.is_empty() {
             error!("No such demo: {}", playing_demo);
             exit(-1);
         }
+        let demo = if is_demo_zip(&demo[0]) {
+            extracted_demos.push(extract_demo(&demo[0])?);
+            extracted_demos[0].demo.clone()
+        } else {
+            demo[0].clone()
+        };
         cmdline.push_line(Line::from_word("-playdemo", 1));
         cmdline.push_line(Line::from_word(
-            demo[0]
-                .to_str()
-                .ok_or_else(|| Error::NonUtf8Path(demo[0].to_string_lossy().into_owned()))?,
+            demo.to_str()
+                .ok_or_else(|| Error::NonUtf8Path(demo.to_string_lossy().into_owned()))?,
             2,
         ));
     }
 
-    if let Some(warp) = matches.value_of("warp") {
-        cmdline.push_line(Line::from_words(
-            &{
-                let mut words = vec!["-warp"];
-                words.extend(warp.split(ARG_SEPARATOR));
-                words
-            },
-            1,
-        ));
+    if let Some(warp) = &warp {
+        cmdline.push_line(Line::from_words(&game.warp_args(warp)?, 1));
+    }
+
+    if let Some(skill) = matches.value_of("skill") {
+        match skill.parse::<u8>() {
+            // 0 means that the engine (or demo) should decide.
+            Ok(0) => {}
+            Ok(skill @ 1..=5) => {
+                cmdline.push_line(Line::from_words(&engine.kind.skill_args(skill), 1))
+            }
+            _ => return Err(Error::BadSkill(skill.to_owned())),
+        }
+    } else if warp.is_some() {
+        cmdline.push_line(Line::from_words(&engine.kind.skill_args(4), 1));
+    }
+
+    if matches.is_present("no-monsters") {
+        cmdline.push_line(Line::from_word("-nomonsters", 1));
+    }
+
+    if matches.is_present("fast") {
+        cmdline.push_line(Line::from_word("-fast", 1));
+    }
+
+    if matches.is_present("respawn") {
+        cmdline.push_line(Line::from_word("-respawn", 1));
+    }
+
+    for arg in audio_args(&matches, rendering) {
+        cmdline.push_line(Line::from_word(arg, 1));
+    }
+
+    if let Some(turbo) = matches.value_of("turbo") {
+        cmdline.push_line(Line::from_words(&turbo_args(turbo)?, 1));
     }
 
-    if let Some(skill) = matches.value_of("skill") {
-        cmdline.push_line(Line::from_words(&[skill_param[0], skill], 1));
-    } else if matches.is_present("warp") {
-        cmdline.push_line(Line::from_words(skill_param, 1));
+    if matches.is_present("deathmatch") {
+        cmdline.push_line(Line::from_word("-deathmatch", 1));
+    } else if matches.is_present("altdeath") {
+        cmdline.push_line(Line::from_word("-altdeath", 1));
     }
 
-    if matches.is_present("no-monsters") {
-        cmdline.push_line(Line::from_word("-nomonsters", 1));
+    if let Some(net) = matches.values_of("net") {
+        let mut words = vec!["-net"];
+        words.extend(net);
+        cmdline.push_line(Line::from_words(&words, 1));
     }
 
-    if matches.is_present("fast") {
-        cmdline.push_line(Line::from_word("-fast", 1));
+    if let Some(timer) = matches.value_of("timer") {
+        cmdline.push_line(Line::from_words(&timer_args(timer)?, 1));
     }
 
-    if matches.is_present("respawn") {
-        cmdline.push_line(Line::from_word("-respawn", 1));
+    if matches.is_present("avg") {
+        cmdline.push_line(Line::from_word("-avg", 1));
+    }
+
+    if let Some(save_dir) = matches.value_of("save-dir") {
+        let save_dir = absolute_path(save_dir)?;
+        create_dir_all(&save_dir).map_err(write_error(&save_dir))?;
+        cmdline.push_line(Line::from_words(
+            &[engine.kind.save_dir_arg(), &save_dir.to_string_lossy()],
+            1,
+        ));
+    }
+
+    if let Some(slot) = matches.value_of("load-game") {
+        cmdline.push_line(Line::from_words(&load_game_args(slot)?, 1));
     }
 
     let dump_dir = DUMP_DIR
         .join("Videos")
         .join(iwad_base)
         .join(viddump_folder_name.join(","));
-
-    let mut renderings = if let Some(rendering) = matches.value_of("render") {
-        rendering
-            .split(':')
+    let manifest_path = matches
+        .value_of("manifest")
+        .map(absolute_path)
+        .transpose()?
+        .unwrap_or_else(|| dump_dir.join(MANIFEST_NAME));
+
+    let mut demos = match matches.values_of("render") {
+        Some(rendering) => rendering
+            .into_iter()
+            .flat_map(split_list)
             .flat_map(|demo| {
                 let results = search_file(demo, FileType::Demo).unwrap_or_else(|e| {
                     error!("{}", e);
@@ -791,65 +2239,204 @@ fn run() -> Result<(), Error> {
                 }
                 results
             })
-            .map(|demo_name| {
-                let video_name = if dump_dir.exists() {
-                    Ok(())
-                } else {
-                    create_dir_all(&dump_dir).map_err(Error::Io)
-                }
-                .and_then(|_| {
+            .collect(),
+        None => vec![],
+    };
+    if let Some(dir) = matches.value_of("render-dir") {
+        let dir = absolute_path(dir)?;
+        let found = demos_in_dir(&dir, matches.value_of("only"))?;
+        if found.is_empty() {
+            info!("No demos to render in '{}'.", dir.to_string_lossy());
+            return Ok(());
+        }
+        demos.extend(found);
+    }
+    let demos = demos
+        .into_iter()
+        .map(|demo| {
+            if is_demo_zip(&demo) {
+                let extracted = extract_demo(&demo)?;
+                let demo = extracted.demo.clone();
+                extracted_demos.push(extracted);
+                Ok(demo)
+            } else {
+                Ok(demo)
+            }
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut renderings = demos
+        .into_iter()
+        .map(|demo_name| {
+            let video_name = if dump_dir.exists() {
+                Ok(())
+            } else {
+                create_dir_all(&dump_dir).map_err(write_error(&dump_dir))
+            }
+            .and_then(|_| {
+                demo_name
+                    .file_stem()
+                    .ok_or_else(|| Error::NoFileStem(demo_name.to_string_lossy().into_owned()))
+            })
+            .map(|viddump_filename| {
+                dump_dir.join({
+                    let mut viddump_filename = viddump_filename.to_os_string();
+                    viddump_filename.push(".mp4");
+                    viddump_filename
+                })
+            });
+            video_name.map(|video_name| -> Result<Job, Error> {
+                Ok(Job::new(
                     demo_name
                         .file_stem()
-                        .ok_or_else(|| Error::NoFileStem(demo_name.to_string_lossy().into_owned()))
-                })
-                .map(|viddump_filename| {
-                    dump_dir.join({
-                        let mut viddump_filename = viddump_filename.to_os_string();
-                        viddump_filename.push(".mp4");
-                        viddump_filename
-                    })
-                });
-                video_name.map(|video_name| -> Result<Job, Error> {
-                    Ok(Job {
-                        name: demo_name
-                            .file_stem()
-                            .ok_or_else(|| {
-                                Error::NoFileStem(demo_name.to_string_lossy().into_owned())
-                            })?
-                            .to_str()
-                            .unwrap()
-                            .to_string(),
-                        video_name,
-                        demo_name,
-                    })
-                })?
-            })
-            .collect::<Result<Vec<_>, _>>()?
-    } else {
-        vec![]
+                        .ok_or_else(|| Error::NoFileStem(demo_name.to_string_lossy().into_owned()))?
+                        .to_str()
+                        .unwrap()
+                        .to_string(),
+                    demo_name,
+                    video_name,
+                ))
+            })?
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    sort_renderings(
+        &mut renderings,
+        matches.value_of("sort"),
+        matches.is_present("reverse"),
+    );
+
+    let engine_log = matches
+        .value_of("engine-log")
+        .map(absolute_path)
+        .transpose()?;
+
+    let jobs = match matches.value_of("jobs") {
+        Some(jobs) => match jobs.parse::<usize>() {
+            Ok(jobs) if jobs > 0 => jobs,
+            _ => return Err(Error::BadJobs(jobs.to_owned())),
+        },
+        None => 1,
     };
 
-    if let Some(passthrough) = matches.values_of("passthrough") {
-        for arg in passthrough {
-            cmdline.push_line(Line::from_word(arg, 1));
+    if matches.is_present("resume") {
+        Manifest::read(&manifest_path)?.skip_rendered(&mut renderings);
+        if renderings.is_empty() {
+            info!("Every demo has already been rendered.");
+            return Ok(());
+        }
+    }
+
+    // A demo played back with the wrong IWAD, PWADs or complevel renders
+    // without any error, but the video is useless.
+    if !renderings.is_empty() {
+        let loaded = pwads
+            .iter_wads()
+            .chain(pwads.iter_dehs())
+            .filter_map(|pwad| pwad.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        let level = match complevel {
+            Complevel::Level(level) => Some(level),
+            Complevel::Auto => None,
+        };
+        let mut checked = vec![];
+        for job in renderings {
+            let risks = desync_risks(&job.demo_name, iwad_base, &loaded, level)?;
+            if risks.is_empty() {
+                checked.push(job);
+                continue;
+            }
+            warn!("'{}' will probably desync:", job.name);
+            for risk in &risks {
+                warn!("  {}", risk);
+            }
+            let render_anyway = !matches.is_present("no-confirm")
+                && Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!("Render '{}' anyway?", job.name))
+                    .default(false)
+                    .interact()
+                    .map_err(Error::Io)?;
+            if render_anyway {
+                checked.push(job);
+            } else {
+                info!("Skipping '{}'", job.name);
+            }
+        }
+        renderings = checked;
+        if renderings.is_empty() {
+            info!("No demos are left to render.");
+            return Ok(());
+        }
+    }
+
+    // Passthrough arguments go last on every command line, after anything we
+    // generate ourselves (including the rendering arguments below).
+    // Arguments from --engine-args-file come before those on the command line.
+    let engine_args = match matches.value_of("engine-args-file") {
+        Some(file) => {
+            let file = absolute_path(file)?;
+            split_args(
+                &file,
+                &std::fs::read_to_string(&file).map_err(read_error(&file))?,
+            )?
         }
+        None => vec![],
+    };
+    let passthrough = engine_args
+        .iter()
+        .map(String::as_str)
+        .chain(matches.values_of("passthrough").unwrap_or_default())
+        .collect::<Vec<_>>();
+
+    if matches.value_of("emit") == Some("json") {
+        cmdline.push_passthrough(&passthrough);
+        let launch = Launch::new(engine, cmdline.iter_words())?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&launch).map_err(Error::JsonSerialize)?
+        );
+        return Ok(());
     }
 
     println!();
     if renderings.is_empty() {
+        cmdline.push_passthrough(&passthrough);
+        println!("Command line: \n'\n{}\n'", cmdline.display(DISPLAY_WIDTH));
+        println!();
+        println!("Engine:     {}", engine_name);
+        println!("IWAD:       {}", iwad);
+        for pwad in pwads.iter_wads().chain(pwads.iter_dehs()) {
+            println!("PWAD:       {}", pwad.to_string_lossy());
+        }
         println!(
-            "Command line: \n'\n{}\n'",
-            cmdline.iter_lines().map(|l| l.iter().join(" ")).join("\n")
+            "Skill:      {}",
+            matches.value_of("skill").unwrap_or("default")
         );
-        Input::<String>::with_theme(&ColorfulTheme {
-            prompt_prefix: style("*".into()).yellow(),
-            ..Default::default()
-        })
-        .with_prompt("Press enter to launch Doom.")
-        .allow_empty(true)
-        .interact()
-        .map_err(Error::Io)?;
-        run_doom(cmdline.iter_words())?;
+        if let Some(warp) = &warp {
+            println!("Warp:       {}", warp.join(" "));
+        }
+        println!(
+            "Complevel:  {}",
+            match complevel {
+                Complevel::Auto => "auto".to_owned(),
+                Complevel::Level(level) => level.to_string(),
+            }
+        );
+        if !matches.is_present("no-confirm")
+            && !Confirm::with_theme(&ColorfulTheme {
+                prompt_prefix: style("*".into()).yellow(),
+                ..Default::default()
+            })
+            .with_prompt("Launch Doom?")
+            .default(true)
+            .interact()
+            .map_err(Error::Io)?
+        {
+            return Ok(());
+        }
+        play(&ProcessLauncher, engine, &cmdline, engine_log.as_deref())?;
+        if !matches.is_present("last") {
+            save_last_launch(|option, value| resolve_for_last(option, value, &iwad, &found_pwads))?;
+        }
     }
     let (job_sender, job_receiver) = channel::<Result<Job, Error>>();
     let (unpause_sender, unpause_receiver) = channel::<()>();
@@ -895,10 +2482,8 @@ fn run() -> Result<(), Error> {
                                                 name.as_os_str().to_string_lossy().into_owned(),
                                             )
                                         })
-                                        .map(|name| Job {
-                                            name: name.to_owned(),
-                                            demo_name: demo_name.clone(),
-                                            video_name,
+                                        .map(|name| {
+                                            Job::new(name.to_owned(), demo_name.clone(), video_name)
                                         }),
                                 )
                                 .map_err(|e| Error::Send(Box::new(e)))
@@ -912,6 +2497,7 @@ fn run() -> Result<(), Error> {
                         name: String::new(),
                         demo_name: PathBuf::new(),
                         video_name: PathBuf::new(),
+                        expected_tics: None,
                     }))
                     .unwrap_or_else(|e| job_sender.send(Err(Error::Send(Box::new(e)))).unwrap());
             }
@@ -925,16 +2511,42 @@ fn run() -> Result<(), Error> {
         }
     })
     .map_err(Error::SignalHandler)?;
+    for job in &renderings {
+        job_event("queued", job);
+    }
+    let play_after_render = matches
+        .is_present("play-after-render")
+        .then(|| matches.value_of("play-after-render").unwrap_or("last"));
+    let mut last_video = None;
+    let mut rendered = |job: Job| {
+        if play_after_render == Some("each") {
+            play_video(&job.video_name);
+        }
+        last_video = Some(job.video_name);
+    };
+    let mut running: Vec<RunningJob> = Vec::new();
     let mut i = 1;
-    while !renderings.is_empty() {
+    while !renderings.is_empty() || !running.is_empty() {
+        if running.len() >= jobs || renderings.is_empty() {
+            for (finished, status) in wait_for_jobs(&mut running)? {
+                if finish_job(&manifest_path, &finished, &engine_name, status)? {
+                    rendered(finished);
+                }
+            }
+            continue;
+        }
+
         info!("====== RENDERING QUEUE ======");
         for job in &renderings {
             info!(
-                "{}  ==>  {}",
+                "{}  ==>  {}{}",
                 job.demo_name.to_str().ok_or_else(|| Error::NonUtf8Path(
                     job.demo_name.to_string_lossy().into_owned()
                 ))?,
-                job.name
+                job.name,
+                job.expected_duration()
+                    .map(|d| format!("  ({}:{:02})", d.as_secs() / 60, d.as_secs() % 60))
+                    .unwrap_or_default()
             );
         }
         info!("==== END RENDERING QUEUE ====");
@@ -957,17 +2569,15 @@ fn run() -> Result<(), Error> {
                 })?,
                 2,
             ));
+            rcmdline.push_passthrough(&passthrough);
             rcmdline
         };
         println!(
             "Command line #{}: \n'\n{}\n'",
             i,
-            render_cmdline
-                .iter_lines()
-                .map(|l| l.iter().join(" "))
-                .join("\n")
+            render_cmdline.display(DISPLAY_WIDTH)
         );
-        if i == 1 {
+        if i == 1 && !matches.is_present("no-confirm") {
             Input::<String>::with_theme(&ColorfulTheme::default())
                 .with_prompt(format!(
                     "Press enter to begin {}rendering.",
@@ -976,7 +2586,7 @@ fn run() -> Result<(), Error> {
                 .allow_empty(true)
                 .interact()
                 .map_err(Error::Io)?;
-        } else {
+        } else if i > 1 && !fast_render {
             CANCELLABLE.store(true, Ordering::SeqCst);
             info!("Continuing batch rendering in 10 seconds. Press <C-c> to add more demos to the queue.");
             sleep(Duration::from_secs(10));
@@ -985,34 +2595,122 @@ fn run() -> Result<(), Error> {
             }
             CANCELLABLE.store(false, Ordering::SeqCst);
             for job in job_receiver.try_iter() {
-                renderings.push(job?);
+                let job = job?;
+                job_event("queued", &job);
+                renderings.push(job);
             }
         }
 
-        run_doom(render_cmdline.iter_words())?;
+        let job_log = engine_log.as_deref().map(|log| job.log_path(log));
+        job_event("started", &job);
+        if jobs == 1 {
+            let status = ProcessLauncher.launch(
+                engine,
+                &render_cmdline.iter_words().collect::<Vec<_>>(),
+                job_log.as_deref(),
+            )?;
+            if finish_job(&manifest_path, &job, &engine_name, status)? {
+                rendered(job);
+            }
+        } else {
+            running.push(RunningJob::spawn(
+                job,
+                engine,
+                render_cmdline.iter_words(),
+                job_log.as_deref(),
+            )?);
+        }
 
         i += 1;
     }
+    if play_after_render == Some("last") {
+        if let Some(video) = last_video {
+            play_video(&video);
+        }
+    }
     Ok(())
 }
 
 fn main() {
-    pretty_env_logger::init();
     if let Err(e) = run() {
         error!("{}", e);
         exit(-1);
     }
 }
 
+/// Lists `errors` one per line, for `Error::Multiple`.
+fn list_errors(errors: &[Error]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("\n    {}", error))
+        .collect()
+}
+
 #[derive(Debug, thiserror::Error)]
 enum Error {
-    #[error("'{file}' contains bad TOML: {error}")]
+    #[error("bad compatibility level '{0}': expected 0-21 or 'auto'")]
+    BadComplevel(String),
+    #[error("bad console variable '{0}': expected NAME=VALUE")]
+    BadCvar(String),
+    #[error(
+        "bad debugger template '{0}': expected {{binary}} after the debugger and {{args}} last"
+    )]
+    BadDebugger(String),
+    #[error("bad geometry '{0}': expected WIDTHxHEIGHT, optionally followed by F or W")]
+    BadGeometry(String),
+    #[error("bad job count '{0}': expected a positive number")]
+    BadJobs(String),
+    #[error("bad save slot '{0}': expected 0-9")]
+    BadSaveSlot(String),
+    #[error("bad search depth '{0}': expected a positive number")]
+    BadSearchDepth(String),
+    #[error("bad skill level '{0}': expected 0-5")]
+    BadSkill(String),
+    #[error("bad timer '{0}': expected a positive number of minutes")]
+    BadTimer(String),
+    #[error("'{file}' contains bad JSON: {error}")]
+    BadJson {
+        file: PathBuf,
+        error: serde_json::Error,
+    },
+    #[error("'{file}' contains bad TOML: {error}{snippet}")]
     BadToml {
         file: PathBuf,
         error: toml::de::Error,
+        /// The line the error is on, if known, ready to print after it.
+        snippet: String,
+    },
+    #[error("bad turbo percentage '{0}': expected 10-255")]
+    BadTurbo(String),
+    #[error("'{file}' holds bad arguments: {error}")]
+    BadLastLaunch { file: PathBuf, error: clap::Error },
+    #[error("bad level '{0}' for this game")]
+    BadWarp(String),
+    #[error("{engine} can't continue '{demo}', which is a {format} demo")]
+    CannotContinueDemo {
+        demo: String,
+        format: DemoFormat,
+        engine: String,
+    },
+    #[error("'{demo}' is a {format} demo and can't be continued at complevel {level}")]
+    ComplevelMismatch {
+        demo: String,
+        format: DemoFormat,
+        level: u8,
     },
     #[error("creating autoloads file in your Doom directory: {0}")]
     CreatingAutoloadsFile(io::Error),
+    #[error("running engines under a debugger is only supported on Unix")]
+    DebugUnsupported,
+    #[error("'{alias}' names both '{}' and '{}'", .engines.0, .engines.1)]
+    DuplicateAlias {
+        alias: String,
+        engines: (String, String),
+    },
+    #[error("the engine exited {}", describe_exit(.code))]
+    EngineExited { code: Option<i32> },
+    #[error("the engine was still running after {0} seconds")]
+    EngineTimedOut(u64),
     #[error("file not found: '{0}'")]
     FileNotFound(String),
     #[error("formatter error: {0}")]
@@ -1021,14 +2719,31 @@ enum Error {
     Homeless,
     #[error("I/O error: {0}")]
     Io(io::Error),
+    #[error("serializing JSON: {0}")]
+    JsonSerialize(serde_json::Error),
+    #[error("engine '{engine}' has no binary at '{binary}'")]
+    MissingBinary { engine: String, binary: PathBuf },
+    #[error("{} problems:{}", .0.len(), list_errors(.0))]
+    Multiple(Vec<Error>),
+    #[error("couldn't find the debugger '{0}' in $PATH")]
+    NoDebugger(String),
+    #[error("no demo (.lmp file) in '{0}'")]
+    NoDemoInZip(String),
+    #[error("no engines of kind {0} defined")]
+    NoEngineOfKind(&'static str),
     #[error("no engines defined")]
     NoEngines,
-    #[error("no file extension in '{0}'")]
-    NoFileExtension(String),
     #[error("no file stem in '{0}'")]
     NoFileStem(String),
-    #[error("attempting to open a file: {0}")]
-    OpeningFile(io::Error),
+    #[error("nothing to replay: '{0}' doesn't exist yet, so launch the game without --last first")]
+    NoLastLaunch(PathBuf),
+    #[error("I/O error reading '{path}': {error}")]
+    ReadingFile { path: PathBuf, error: io::Error },
+    #[error("reading zip '{path}': {error}")]
+    ReadingZip {
+        path: PathBuf,
+        error: zip::result::ZipError,
+    },
     #[error("receiving from interrupt handler: {0}")]
     Recv(#[from] RecvError),
     #[error("could not run Doom: {0}")]
@@ -1037,8 +2752,723 @@ enum Error {
     Send(Box<SendError<Result<Job, Error>>>),
     #[error("handling interrupt: {0}")]
     SignalHandler(ctrlc::Error),
+    #[error("serializing TOML: {0}")]
+    TomlSerialize(toml::ser::Error),
+    #[error("not a directory: '{0}'")]
+    NotADirectory(String),
     #[error("non-UTF-8 path: '{0}'")]
     NonUtf8Path(String),
+    #[error("unknown demo format in '{0}'")]
+    UnknownDemoFormat(String),
+    #[error("unknown engine '{0}'")]
+    UnknownEngine(String),
+    #[error("unknown engine kind '{0}'")]
+    UnknownEngineKind(String),
+    #[error("unknown profile '{0}'")]
+    UnknownProfile(String),
+    #[error("unterminated quote in '{file}' on line {line}")]
+    UnterminatedQuote { file: PathBuf, line: usize },
     #[error("walking directory: {0}")]
     WalkDir(#[from] walkdir::Error),
+    #[error("I/O error writing '{path}': {error}")]
+    WritingFile { path: PathBuf, error: io::Error },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launch::MockLauncher;
+
+    #[test]
+    fn dedup_resolves_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("maps")).unwrap();
+        for file in ["a.wad", "b.wad", "a.deh"] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        let mut pwads = Pwads::with_wads(vec![
+            dir.path().join("a.wad"),
+            dir.path().join("maps/../b.wad"),
+            dir.path().join("maps/../a.wad"),
+            dir.path().join("./b.wad"),
+        ]);
+        pwads.add_dehs(vec![dir.path().join("a.deh"), dir.path().join("./a.deh")]);
+        pwads.dedup();
+        assert_eq!(
+            pwads.iter_wads().collect::<Vec<_>>(),
+            [dir.path().join("a.wad"), dir.path().join("maps/../b.wad")]
+        );
+        assert_eq!(
+            pwads.iter_dehs().collect::<Vec<_>>(),
+            [dir.path().join("a.deh")]
+        );
+    }
+
+    #[test]
+    fn loads_saved_games() {
+        assert_eq!(load_game_args("3").unwrap(), ["-loadgame", "3"]);
+        assert_eq!(load_game_args("0").unwrap(), ["-loadgame", "0"]);
+        for slot in ["10", "-1", "three", ""] {
+            assert!(matches!(load_game_args(slot), Err(Error::BadSaveSlot(_))));
+        }
+    }
+
+    #[test]
+    fn cannot_load_game_while_recording() {
+        let error = app()
+            .get_matches_from_safe(["playdoom", "--load-game", "3", "--record", "run.lmp"])
+            .unwrap_err();
+        assert_eq!(error.kind, clap::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn turbo_range() {
+        assert_eq!(turbo_args("10").unwrap(), ["-turbo", "10"]);
+        assert_eq!(turbo_args("255").unwrap(), ["-turbo", "255"]);
+        for turbo in ["9", "256", "0", "fast"] {
+            assert!(matches!(turbo_args(turbo), Err(Error::BadTurbo(_))));
+        }
+    }
+
+    #[test]
+    fn timer_must_be_positive() {
+        assert_eq!(timer_args("20").unwrap(), ["-timer", "20"]);
+        for timer in ["0", "-5", "1.5", ""] {
+            assert!(matches!(timer_args(timer), Err(Error::BadTimer(_))));
+        }
+    }
+
+    #[test]
+    fn avg_conflicts_with_timer() {
+        assert!(app()
+            .get_matches_from_safe(["playdoom", "--avg"])
+            .unwrap()
+            .is_present("avg"));
+        let error = app()
+            .get_matches_from_safe(["playdoom", "--avg", "--timer", "10"])
+            .unwrap_err();
+        assert_eq!(error.kind, clap::ErrorKind::ArgumentConflict);
+    }
+
+    /// Parses `args` as given on the command line, without a last launch or
+    /// profile.
+    fn options(args: &[&str]) -> Options<'static> {
+        let matches = app()
+            .get_matches_from_safe(std::iter::once("playdoom").chain(args.iter().copied()))
+            .unwrap();
+        Options::new(matches, None, Profile::default())
+    }
+
+    fn audio(args: &[&str], rendering: bool) -> Vec<&'static str> {
+        audio_args(&options(args), rendering)
+    }
+
+    #[test]
+    fn rendering_is_silent_by_default() {
+        assert_eq!(audio(&[], true), ["-nosound"]);
+        assert!(audio(&["--sound"], true).is_empty());
+        assert_eq!(audio(&["--sound", "--nomusic"], true), ["-nomusic"]);
+    }
+
+    #[test]
+    fn playing_has_sound_by_default() {
+        assert!(audio(&[], false).is_empty());
+        assert_eq!(audio(&["--nosound"], false), ["-nosound"]);
+        assert_eq!(
+            audio(&["--nomusic", "--nosfx"], false),
+            ["-nomusic", "-nosfx"]
+        );
+        assert_eq!(audio(&["--nosound", "--nomusic"], false), ["-nosound"]);
+    }
+
+    #[test]
+    fn iwads_dir_is_searched_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let iwads = dir.path().join("iwads");
+        std::fs::create_dir(&iwads).unwrap();
+        std::fs::write(iwads.join("DOOM2.WAD"), "").unwrap();
+        std::fs::write(dir.path().join("DOOM2.WAD"), "").unwrap();
+
+        let search_dirs = iwad_search_dirs(vec![iwads.clone()], dir.path().to_owned());
+        assert_eq!(search_dirs[..2], [iwads.clone(), dir.path().to_owned()]);
+        let found =
+            search_file_in_dirs_by(PathBuf::from("doom2"), FileType::Iwad, search_dirs, |_| {
+                true
+            })
+            .unwrap();
+        assert_eq!(found, [iwads.join("DOOM2.WAD")]);
+    }
+
+    #[test]
+    fn collects_wads_and_dehs_by_extension() {
+        let pwads = ["a.wad", "a.deh", "b.pk3", "c.BEX", "d"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Pwads>();
+        assert_eq!(pwads.len(), 5);
+        assert!(!pwads.is_empty());
+        assert_eq!(
+            pwads.iter_wads().collect::<Vec<_>>(),
+            [Path::new("a.wad"), Path::new("b.pk3"), Path::new("d")]
+        );
+        assert_eq!(
+            pwads.iter_dehs().collect::<Vec<_>>(),
+            [Path::new("a.deh"), Path::new("c.BEX")]
+        );
+        assert!(Pwads::new().is_empty());
+        assert_eq!(
+            Pwads::with_wads(vec![PathBuf::from("a.deh")])
+                .iter_wads()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn dehs_load_after_their_wads() {
+        let pwads = [
+            "maps/a.wad",
+            "maps/b.wad",
+            "maps/c.wad",
+            "maps/A.deh",
+            "maps/c.bex",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Pwads>();
+        let path = Path::new;
+        assert_eq!(
+            pwads.load_order(),
+            [
+                (vec![path("maps/a.wad")], vec![path("maps/A.deh")]),
+                (
+                    vec![path("maps/b.wad"), path("maps/c.wad")],
+                    vec![path("maps/c.bex")]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unpatched_dehs_load_last() {
+        let pwads = ["maps/a.wad", "patches/a.deh", "maps/b.wad", "fixes.deh"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Pwads>();
+        let path = Path::new;
+        assert_eq!(
+            pwads.load_order(),
+            [(
+                vec![path("maps/a.wad"), path("maps/b.wad")],
+                vec![path("patches/a.deh"), path("fixes.deh")]
+            )]
+        );
+    }
+
+    #[test]
+    fn mounts_only_on_zdoom() {
+        let dir = tempfile::tempdir().unwrap();
+        let mount = dir.path().to_string_lossy().into_owned();
+
+        let mut pwads = Pwads::new();
+        add_mounts(&mut pwads, DoomEngineKind::ZDoom, &[&mount]).unwrap();
+        assert_eq!(pwads.iter_wads().collect::<Vec<_>>(), [dir.path()]);
+
+        let mut pwads = Pwads::new();
+        add_mounts(&mut pwads, DoomEngineKind::Boom, &[&mount]).unwrap();
+        assert!(pwads.is_empty());
+    }
+
+    #[test]
+    fn mount_must_be_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.wad");
+        std::fs::write(&file, "").unwrap();
+        let mut pwads = Pwads::new();
+        assert!(matches!(
+            add_mounts(
+                &mut pwads,
+                DoomEngineKind::ZDoom,
+                &[&file.to_string_lossy()]
+            ),
+            Err(Error::NotADirectory(_))
+        ));
+    }
+
+    #[test]
+    fn auto_names_demos_after_level_and_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = record_path(
+            &options(&["--record"]),
+            Game::Doom2,
+            Some(&["7"]),
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!(path.parent(), Some(dir.path()));
+        // map07-20240115-2230.lmp
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let (level, time) = name.strip_suffix(".lmp").unwrap().split_at(6);
+        assert_eq!(level, "map07-");
+        let (date, time) = time.split_once('-').unwrap();
+        assert_eq!(date.len(), 8);
+        assert_eq!(time.len(), 4);
+        assert!(date.chars().chain(time.chars()).all(|c| c.is_ascii_digit()));
+
+        let path = record_path(
+            &options(&["--record"]),
+            Game::Doom,
+            Some(&["2", "5"]),
+            dir.path(),
+        )
+        .unwrap();
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("e2m5-"));
+    }
+
+    #[test]
+    fn records_to_the_given_demo() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            record_path(
+                &options(&["--record", "run1"]),
+                Game::Doom2,
+                None,
+                dir.path()
+            )
+            .unwrap(),
+            dir.path().join("run1")
+        );
+    }
+
+    #[test]
+    fn sets_cvars_on_zdoom() {
+        let mut cmdline = CommandLine::new();
+        add_cvars(
+            &mut cmdline,
+            DoomEngineKind::ZDoom,
+            &["vid_fps=1", "sv_motd=a=b", "empty="],
+        )
+        .unwrap();
+        assert_eq!(
+            cmdline.iter_words().collect::<Vec<_>>(),
+            ["+vid_fps", "1", "+sv_motd", "a=b", "+empty", ""]
+        );
+
+        let mut cmdline = CommandLine::new();
+        add_cvars(&mut cmdline, DoomEngineKind::Boom, &["vid_fps=1"]).unwrap();
+        assert_eq!(cmdline.iter_words().count(), 0);
+    }
+
+    #[test]
+    fn rejects_bad_cvars() {
+        for cvar in ["vid_fps", "=1"] {
+            assert!(
+                matches!(
+                    add_cvars(&mut CommandLine::new(), DoomEngineKind::ZDoom, &[cvar]),
+                    Err(Error::BadCvar(_))
+                ),
+                "{}",
+                cvar
+            );
+        }
+    }
+
+    #[test]
+    fn organizes_demos_by_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let organized = options(&["--record", "run1.lmp", "--organize-demos"]);
+        let path = record_path(&organized, Game::Doom2, Some(&["3"]), dir.path()).unwrap();
+        assert_eq!(path, dir.path().join("map03").join("run1.lmp"));
+        assert!(dir.path().join("map03").is_dir());
+
+        let flat = options(&["--record", "run1.lmp"]);
+        assert_eq!(
+            record_path(&flat, Game::Doom2, Some(&["3"]), dir.path()).unwrap(),
+            dir.path().join("run1.lmp")
+        );
+        assert_eq!(
+            record_path(&organized, Game::Doom2, None, dir.path()).unwrap(),
+            dir.path().join("run1.lmp")
+        );
+    }
+
+    /// Renderings of demos written with `contents` and modified at the given
+    /// times, named after their contents.
+    fn renderings_of(dir: &Path, demos: &[(&str, u64)]) -> Vec<Job> {
+        demos
+            .iter()
+            .map(|(contents, mtime)| {
+                let demo = dir.join(format!("{}.lmp", contents));
+                let file = File::create(&demo).unwrap();
+                (&file).write_all(contents.as_bytes()).unwrap();
+                file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(*mtime))
+                    .unwrap();
+                Job::new(contents.to_string(), demo, dir.join("video.mp4"))
+            })
+            .collect()
+    }
+
+    fn sorted<'r>(renderings: &'r mut [Job], sort: Option<&str>, reverse: bool) -> Vec<&'r str> {
+        sort_renderings(renderings, sort, reverse);
+        renderings.iter().map(|job| job.name.as_str()).collect()
+    }
+
+    #[test]
+    fn sorts_renderings() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut renderings = renderings_of(dir.path(), &[("bb", 300), ("a", 100), ("ccc", 200)]);
+        assert_eq!(sorted(&mut renderings, None, false), ["bb", "a", "ccc"]);
+        assert_eq!(sorted(&mut renderings, None, true), ["ccc", "a", "bb"]);
+        assert_eq!(
+            sorted(&mut renderings, Some("name"), false),
+            ["a", "bb", "ccc"]
+        );
+        assert_eq!(
+            sorted(&mut renderings, Some("mtime"), false),
+            ["a", "ccc", "bb"]
+        );
+        assert_eq!(
+            sorted(&mut renderings, Some("size"), false),
+            ["a", "bb", "ccc"]
+        );
+        assert_eq!(
+            sorted(&mut renderings, Some("size"), true),
+            ["ccc", "bb", "a"]
+        );
+    }
+
+    #[test]
+    fn reorders_wads_by_load_order_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("load-order.txt");
+        std::fs::write(
+            &file,
+            "# megawad, then gameplay, then fixes\nsunlust.wad\n\n  smooth  \nFIX.WAD\nmissing\n",
+        )
+        .unwrap();
+        let names = read_load_order(&file).unwrap();
+        assert_eq!(names, ["sunlust.wad", "smooth", "FIX.WAD", "missing"]);
+
+        let mut pwads = Pwads::with_wads(vec![
+            PathBuf::from("/doom/fix.wad"),
+            PathBuf::from("/doom/music.wad"),
+            PathBuf::from("/doom/smooth.pk3"),
+            PathBuf::from("/doom/sunlust.wad"),
+        ]);
+        assert_eq!(pwads.reorder(&names), ["missing"]);
+        assert_eq!(
+            pwads.iter_wads().collect::<Vec<_>>(),
+            [
+                Path::new("/doom/sunlust.wad"),
+                Path::new("/doom/smooth.pk3"),
+                Path::new("/doom/fix.wad"),
+                Path::new("/doom/music.wad"),
+            ]
+        );
+    }
+
+    #[test]
+    fn scored_results_are_best_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let other_dir = tempfile::tempdir().unwrap();
+        for file in ["doom2.deh", "doom2.wad", "DOOM2.WAD", "sunlust.wad"] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        std::fs::write(other_dir.path().join("DOOM2.WAD"), "").unwrap();
+
+        let results = search_file_scored_in_dirs(
+            Path::new("DOOM2.WAD"),
+            FileType::Iwad,
+            &[dir.path().to_path_buf(), other_dir.path().to_path_buf()],
+        )
+        .unwrap();
+        let paths = results.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                &dir.path().join("DOOM2.WAD"),
+                &dir.path().join("doom2.wad"),
+                &dir.path().join("doom2.deh"),
+                &other_dir.path().join("DOOM2.WAD"),
+            ]
+        );
+        // Within a directory, a better match always scores higher.
+        assert!(results[0].1 > results[1].1);
+        assert!(results[1].1 > results[2].1);
+        assert_eq!(results[3].1, results[0].1);
+    }
+
+    #[test]
+    fn scoring_a_dir_matches_scoring_each_file() {
+        let dir = tempfile::tempdir().unwrap();
+        for megawad in 0..20 {
+            let megawad_dir = dir.path().join(format!("megawad{}", megawad));
+            std::fs::create_dir(&megawad_dir).unwrap();
+            for map in 0..50 {
+                let name = format!("map{:02}.{}", map, ["wad", "deh", "txt"][map % 3]);
+                std::fs::write(megawad_dir.join(name), "").unwrap();
+            }
+        }
+        let name = Path::new("map07.wad");
+
+        // With the parallel-search feature, score_dir uses several threads.
+        let scored = score_dir(name, FileType::Pwad, dir.path(), |_| true).unwrap();
+        let expected = SEARCH_INDEX
+            .lock()
+            .unwrap()
+            .files(dir.path())
+            .unwrap()
+            .iter()
+            .filter_map(|entry| {
+                let score = score_entry(name, entry, FileType::Pwad).unwrap();
+                (score > MIN_SCORE).then(|| (entry.clone(), score))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(scored.len(), 20);
+        assert_eq!(scored, expected);
+    }
+
+    fn prboom_plus() -> DoomEngine {
+        DoomEngine::new(
+            "prboom-plus",
+            PathBuf::from("/usr/games/prboom-plus"),
+            DoomEngineKind::Boom,
+        )
+    }
+
+    #[test]
+    fn plays_the_whole_command_line() {
+        let mut cmdline = CommandLine::new();
+        cmdline.push_line(Line::from_word("/usr/games/prboom-plus", 0));
+        cmdline.push_line(Line::from_words(&["-iwad", "/home/me/doom/DOOM2.WAD"], 1));
+        cmdline.push_line(Line::from_words(&["-file", "a.wad", "b.wad"], 1));
+        cmdline.push_passthrough(&["-nosound"]);
+        let launcher = MockLauncher::default();
+        play(&launcher, &prboom_plus(), &cmdline, None).unwrap();
+        assert_eq!(
+            *launcher.argvs.borrow(),
+            [[
+                "/usr/games/prboom-plus",
+                "-iwad",
+                "/home/me/doom/DOOM2.WAD",
+                "-file",
+                "a.wad",
+                "b.wad",
+                "-nosound",
+            ]]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_engine_failure() {
+        use std::os::unix::process::ExitStatusExt;
+        let launcher = MockLauncher {
+            status: ExitStatus::from_raw(3 << 8),
+            ..Default::default()
+        };
+        let mut cmdline = CommandLine::new();
+        cmdline.push_line(Line::from_word("/usr/games/prboom-plus", 0));
+        assert!(matches!(
+            play(&launcher, &prboom_plus(), &cmdline, None),
+            Err(Error::EngineExited { code: Some(3) })
+        ));
+    }
+
+    #[test]
+    fn asks_engine_for_version() {
+        let launcher = MockLauncher {
+            output: "PrBoom-Plus v2.6.2\n".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            engine_version(&launcher, &prboom_plus()).unwrap(),
+            "PrBoom-Plus v2.6.2"
+        );
+        assert_eq!(*launcher.argvs.borrow(), [["/usr/games/prboom-plus", "-v"]]);
+    }
+
+    #[test]
+    fn engine_info_only_runs_engines_with_version_args() {
+        let launcher = MockLauncher::default();
+        print_engine_info(&launcher, "prboom-plus", &prboom_plus());
+        assert!(launcher.argvs.borrow().is_empty());
+    }
+
+    #[test]
+    fn runs_engine_under_debugger() {
+        let mut engine = prboom_plus();
+        engine.required_args = vec!["-nowarn".to_owned()];
+        let debugger = Debugger {
+            before_binary: vec!["/usr/bin/lldb".to_owned()],
+            before_args: vec!["--".to_owned()],
+        };
+        let mut cmdline = engine_cmdline(&engine, Some(&debugger)).unwrap();
+        cmdline.push_line(Line::from_words(&["-iwad", "DOOM2.WAD"], 1));
+        let launcher = MockLauncher::default();
+        play(&launcher, &engine, &cmdline, None).unwrap();
+        assert_eq!(
+            *launcher.argvs.borrow(),
+            [[
+                "/usr/bin/lldb",
+                "/usr/games/prboom-plus",
+                "--",
+                "-nowarn",
+                "-iwad",
+                "DOOM2.WAD",
+            ]]
+        );
+    }
+
+    #[test]
+    fn runs_engine_without_debugger() {
+        let cmdline = engine_cmdline(&prboom_plus(), None).unwrap();
+        assert_eq!(
+            cmdline.iter_words().collect::<Vec<_>>(),
+            ["/usr/games/prboom-plus"]
+        );
+    }
+
+    #[test]
+    fn longtics_only_on_boom_engines() {
+        for kind in DoomEngineKind::ALL {
+            let boom = matches!(
+                kind,
+                DoomEngineKind::Boom | DoomEngineKind::MBF | DoomEngineKind::Eternity
+            );
+            let expected = |arg| if boom { Some(arg) } else { None };
+            assert_eq!(
+                tics_arg(kind, true, false),
+                expected("-longtics"),
+                "{:?}",
+                kind
+            );
+            assert_eq!(
+                tics_arg(kind, false, true),
+                expected("-shorttics"),
+                "{:?}",
+                kind
+            );
+            assert_eq!(tics_arg(kind, true, true), None, "{:?}", kind);
+            assert_eq!(tics_arg(kind, false, false), None, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn iwad_parent_is_searched_first_for_pwads() {
+        let dir = tempfile::tempdir().unwrap();
+        let total_conversion = dir.path().join("tc");
+        let elsewhere = dir.path().join("pwads");
+        for subdir in [&total_conversion, &elsewhere] {
+            std::fs::create_dir(subdir).unwrap();
+            std::fs::write(subdir.join("maps.wad"), "").unwrap();
+        }
+        std::fs::write(total_conversion.join("tc.wad"), "").unwrap();
+
+        let search_dirs = pwad_search_dirs(Some(&total_conversion), elsewhere.clone());
+        assert_eq!(search_dirs[0], total_conversion);
+        assert_eq!(search_dirs[1], elsewhere);
+        let found = search_pwad("maps", &search_dirs).unwrap();
+        assert_eq!(found, [total_conversion.join("maps.wad")]);
+    }
+
+    #[test]
+    fn recording_keeps_existing_demos() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("run.lmp"), "a good run").unwrap();
+        let record =
+            |args: &[&str]| record_path(&options(args), Game::Doom2, None, dir.path()).unwrap();
+        assert_eq!(
+            record(&["--record", "run.lmp"]),
+            dir.path().join("run-2.lmp")
+        );
+        std::fs::write(dir.path().join("run-2.lmp"), "another").unwrap();
+        assert_eq!(
+            record(&["--record", "run.lmp"]),
+            dir.path().join("run-3.lmp")
+        );
+        assert_eq!(
+            record(&["--record", "run.lmp", "--record-overwrite"]),
+            dir.path().join("run.lmp")
+        );
+    }
+
+    #[test]
+    fn engine_kind_conflicts_with_engine() {
+        let error = app()
+            .get_matches_from_safe(["playdoom", "--engine", "gzdoom", "--engine-kind", "Boom"])
+            .unwrap_err();
+        assert_eq!(error.kind, clap::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn finds_wads_inside_archives_only_when_asked() {
+        use std::io::Write;
+        let dir = tempfile::tempdir().unwrap();
+        let collection = dir.path().join("collection.pk3");
+        let mut zip = zip::ZipWriter::new(File::create(&collection).unwrap());
+        zip.start_file("foo.wad", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(b"PWAD").unwrap();
+        zip.finish().unwrap();
+        let search_dirs = vec![dir.path().to_owned()];
+
+        // Without --search-archives, only files on disk are found.
+        assert!(matches!(
+            search_file_in_dirs_by(
+                PathBuf::from("foo.wad"),
+                FileType::Pwad,
+                search_dirs.clone(),
+                |_| true
+            ),
+            Err(Error::FileNotFound(_))
+        ));
+        assert_eq!(
+            search_archives(Path::new("foo.wad"), FileType::Pwad, &search_dirs, |_| true).unwrap(),
+            Some(collection)
+        );
+    }
+
+    #[test]
+    fn resolves_paths_for_last() {
+        let doom_dir = doom_dir().unwrap();
+        let found_pwads = [
+            (
+                "foo",
+                vec![doom_dir.join("foo.wad"), doom_dir.join("foo.deh")],
+            ),
+            ("./bar.wad", vec![doom_dir.join("pwads/bar.wad")]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let resolve = |option, value| {
+            resolve_for_last(option, value, "/usr/share/doom/DOOM2.WAD", &found_pwads).unwrap()
+        };
+        assert_eq!(resolve("iwad", "doom2"), "/usr/share/doom/DOOM2.WAD");
+        assert_eq!(
+            resolve("pwads", "foo,./bar.wad"),
+            format!(
+                "{},{},{}",
+                doom_dir.join("foo.wad").to_string_lossy(),
+                doom_dir.join("foo.deh").to_string_lossy(),
+                doom_dir.join("pwads/bar.wad").to_string_lossy()
+            )
+        );
+        assert_eq!(
+            resolve("demo-dir", "demos"),
+            doom_dir.join("demos").to_string_lossy()
+        );
+        assert_eq!(
+            resolve("iwad-dir", "iwads,/usr/share/doom"),
+            format!(
+                "{},/usr/share/doom",
+                doom_dir.join("iwads").to_string_lossy()
+            )
+        );
+    }
 }
@@ -0,0 +1,137 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use log::info;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::job::Job;
+use crate::util::read_error;
+use crate::util::write_error;
+use crate::Error;
+
+/// The manifest's file name in the dump directory, unless `--manifest` says otherwise.
+pub(crate) const MANIFEST_NAME: &str = "renders.json";
+
+/// A completed render.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct RenderRecord {
+    pub demo: PathBuf,
+    pub video: PathBuf,
+    pub engine: String,
+    pub completed: String,
+}
+
+/// The record of completed renders kept alongside the videos.
+#[derive(Deserialize, Serialize, Default)]
+pub(crate) struct Manifest {
+    #[serde(default)]
+    renders: Vec<RenderRecord>,
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_slice(&std::fs::read(path).map_err(read_error(path))?).map_err(|error| {
+            Error::BadJson {
+                file: path.to_path_buf(),
+                error,
+            }
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let contents = serde_json::to_string_pretty(self).map_err(Error::JsonSerialize)?;
+        std::fs::write(path, contents).map_err(write_error(path))
+    }
+
+    /// Whether `job`'s video was finished in an earlier run.
+    ///
+    /// The video has to be recorded here as well as exist, so that one left
+    /// half-written by a crash is rendered again.
+    pub fn is_rendered(&self, job: &Job) -> bool {
+        let on_disk = std::fs::metadata(&job.video_name)
+            .map(|metadata| metadata.len() > 0)
+            .unwrap_or(false);
+        on_disk
+            && self
+                .renders
+                .iter()
+                .any(|record| record.video == job.video_name)
+    }
+
+    /// Drops the jobs in `jobs` that were rendered in an earlier run.
+    pub fn skip_rendered(&self, jobs: &mut Vec<Job>) {
+        jobs.retain(|job| {
+            let rendered = self.is_rendered(job);
+            if rendered {
+                info!(
+                    "Skipping '{}': already rendered to '{}'",
+                    job.name,
+                    job.video_name.to_string_lossy()
+                );
+            }
+            !rendered
+        });
+    }
+}
+
+/// Adds `job`, just rendered with `engine`, to the manifest at `path`.
+pub(crate) fn record_render(path: &Path, job: &Job, engine: &str) -> Result<(), Error> {
+    let mut manifest = Manifest::read(path)?;
+    manifest.renders.push(RenderRecord {
+        demo: job.demo_name.clone(),
+        video: job.video_name.clone(),
+        engine: engine.to_owned(),
+        completed: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+    });
+    manifest.write(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(dir: &Path, name: &str) -> Job {
+        Job {
+            name: name.to_owned(),
+            demo_name: dir.join(name).with_extension("lmp"),
+            video_name: dir.join(name).with_extension("mp4"),
+            expected_tics: None,
+        }
+    }
+
+    #[test]
+    fn skips_rendered_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join(MANIFEST_NAME);
+        let done = job(dir.path(), "map01");
+        std::fs::write(&done.video_name, "video").unwrap();
+        record_render(&manifest_path, &done, "prboom-plus").unwrap();
+        // Left behind by a crash, so not in the manifest.
+        let crashed = job(dir.path(), "map02");
+        std::fs::write(&crashed.video_name, "vid").unwrap();
+
+        let mut jobs = vec![done, crashed, job(dir.path(), "map03")];
+        Manifest::read(&manifest_path)
+            .unwrap()
+            .skip_rendered(&mut jobs);
+        let names = jobs.iter().map(|job| job.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["map02", "map03"]);
+    }
+
+    #[test]
+    fn rerenders_empty_videos() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join(MANIFEST_NAME);
+        let empty = job(dir.path(), "map01");
+        std::fs::write(&empty.video_name, "").unwrap();
+        record_render(&manifest_path, &empty, "prboom-plus").unwrap();
+
+        let manifest = Manifest::read(&manifest_path).unwrap();
+        assert!(!manifest.is_rendered(&empty));
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::fs::File;
+
+use clap::ArgMatches;
+use indoc::indoc;
+use log::trace;
+use log::warn;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::util::read_error;
+use crate::util::toml_error;
+use crate::util::write_error;
+use crate::Error;
+
+const EXAMPLE_PROFILES_FILE: &str = indoc!(
+    r#"
+    # Each header is the name of a profile, used with the --profile option.
+    # Keys are the long names of command-line options; explicit options on the
+    # command line always take precedence over the values in a profile.
+    [example]
+    iwad = "DOOM2.WAD"
+    pwads = "example.wad"
+    skill = "4"
+    compatibility-level = "9"
+    # Options that don't take a value are switched on with true.
+    fast = false
+    "#
+);
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub(crate) enum ProfileValue {
+    Flag(bool),
+    Value(String),
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub(crate) struct Profile {
+    options: HashMap<String, ProfileValue>,
+}
+
+impl Profile {
+    pub fn value_of(&self, name: &str) -> Option<&str> {
+        match self.options.get(name)? {
+            ProfileValue::Value(value) => Some(value),
+            ProfileValue::Flag(_) => None,
+        }
+    }
+
+    pub fn is_present(&self, name: &str) -> bool {
+        match self.options.get(name) {
+            Some(ProfileValue::Flag(flag)) => *flag,
+            Some(ProfileValue::Value(_)) => true,
+            None => false,
+        }
+    }
+}
+
+/// Command-line options layered on top of those of the last launch, if
+/// replaying it, and then an optional profile.
+pub(crate) struct Options<'a> {
+    matches: ArgMatches<'a>,
+    last: Option<ArgMatches<'a>>,
+    profile: Profile,
+}
+
+impl<'a> Options<'a> {
+    pub fn new(matches: ArgMatches<'a>, last: Option<ArgMatches<'a>>, profile: Profile) -> Self {
+        Self {
+            matches,
+            last,
+            profile,
+        }
+    }
+
+    pub fn value_of(&self, name: &str) -> Option<&str> {
+        self.matches
+            .value_of(name)
+            .or_else(|| self.last.as_ref().and_then(|last| last.value_of(name)))
+            .or_else(|| self.profile.value_of(name))
+    }
+
+    pub fn is_present(&self, name: &str) -> bool {
+        self.matches.is_present(name)
+            || self.last.as_ref().is_some_and(|last| last.is_present(name))
+            || self.profile.is_present(name)
+    }
+
+    pub fn values_of(&self, name: &str) -> Option<Vec<&str>> {
+        self.matches
+            .values_of(name)
+            .or_else(|| self.last.as_ref().and_then(|last| last.values_of(name)))
+            .map(|values| values.collect())
+            .or_else(|| self.profile.value_of(name).map(|value| vec![value]))
+    }
+}
+
+pub(crate) fn read_profiles() -> Result<HashMap<String, Profile>, Error> {
+    let profiles_path = crate::config_dir()?.join("profiles.toml");
+    trace!(
+        "Searching for launch profiles in {}",
+        profiles_path.to_string_lossy()
+    );
+    if !profiles_path.exists() {
+        warn!("Path not found, creating template.");
+        let mut f = File::create(&profiles_path).map_err(write_error(&profiles_path))?;
+
+        use std::io::Write;
+        write!(f, "{}", EXAMPLE_PROFILES_FILE).map_err(write_error(&profiles_path))?;
+    }
+
+    let contents = std::fs::read(profiles_path.as_path()).map_err(read_error(&profiles_path))?;
+    toml::from_slice(&contents).map_err(toml_error(&profiles_path, &contents))
+}
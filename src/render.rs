@@ -0,0 +1,171 @@
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::process::Child;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::engine::DoomEngine;
+use crate::job::Job;
+use crate::launch::doom_command;
+use crate::Error;
+
+/// How often to check whether any running engine has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An engine process rendering a job alongside others.
+pub(crate) struct RunningJob {
+    job: Job,
+    child: Child,
+    output: Vec<JoinHandle<()>>,
+}
+
+impl RunningJob {
+    /// Starts rendering `job` without waiting for it to finish.
+    ///
+    /// Everything the engine prints goes to `log` if given, or is otherwise
+    /// prefixed with the job's name, so that the output of several engines can
+    /// be told apart. The engine gets a process group of its own so that
+    /// pressing <C-c> to add more demos doesn't interrupt it.
+    pub fn spawn<'l>(
+        job: Job,
+        engine: &DoomEngine,
+        cmdline: impl Iterator<Item = &'l str>,
+        log: Option<&Path>,
+    ) -> Result<Self, Error> {
+        let mut command = doom_command(engine, cmdline, log)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        if log.is_none() {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        let mut child = command.spawn().map_err(Error::RunningDoom)?;
+        let prefix = format!("[{}]", job.name);
+        let mut output = Vec::with_capacity(2);
+        if let Some(stdout) = child.stdout.take() {
+            output.push(prefix_lines(stdout, prefix.clone(), std::io::stdout()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            output.push(prefix_lines(stderr, prefix, std::io::stderr()));
+        }
+        Ok(Self { job, child, output })
+    }
+}
+
+/// Waits until at least one of the `running` jobs has finished, and returns
+/// every job that has, with how its engine exited.
+pub(crate) fn wait_for_jobs(
+    running: &mut Vec<RunningJob>,
+) -> Result<Vec<(Job, ExitStatus)>, Error> {
+    loop {
+        let mut finished = vec![];
+        let mut i = 0;
+        while i < running.len() {
+            if let Some(status) = running[i].child.try_wait().map_err(Error::RunningDoom)? {
+                let done = running.remove(i);
+                for output in done.output {
+                    // The engine has exited, so its pipes are closed and
+                    // these threads are about to stop anyway.
+                    let _ = output.join();
+                }
+                finished.push((done.job, status));
+            } else {
+                i += 1;
+            }
+        }
+        if !finished.is_empty() {
+            return Ok(finished);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Copies `stream` to `out` (our stdout or stderr) line by line, behind
+/// `prefix`.
+fn prefix_lines(
+    stream: impl Read + Send + 'static,
+    prefix: String,
+    mut out: impl Write + Send + 'static,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(stream).split(b'\n') {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches('\r');
+            let _ = writeln!(out, "{} {}", prefix, line);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    use crate::engine::DoomEngineKind;
+
+    #[test]
+    fn prefixes_each_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.txt");
+        let stream = std::io::Cursor::new(b"one\r\ntwo\n\nthree".to_vec());
+        prefix_lines(stream, "[map01]".to_owned(), File::create(&out).unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "[map01] one\n[map01] two\n[map01] \n[map01] three\n"
+        );
+    }
+
+    fn job(name: &str) -> Job {
+        Job {
+            name: name.to_owned(),
+            demo_name: PathBuf::from(format!("{}.lmp", name)),
+            video_name: PathBuf::from(format!("{}.mp4", name)),
+            expected_tics: None,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn waits_for_the_jobs_that_finish() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("render.log");
+        let sh = DoomEngine::new("sh", PathBuf::from("/bin/sh"), DoomEngineKind::Boom);
+        let spawn = |name, script| {
+            RunningJob::spawn(
+                job(name),
+                &sh,
+                ["/bin/sh", "-c", script].iter().copied(),
+                Some(&log),
+            )
+            .unwrap()
+        };
+        let mut running = vec![spawn("slow", "sleep 10"), spawn("fast", "exit 3")];
+
+        let finished = wait_for_jobs(&mut running).unwrap();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].0.name, "fast");
+        assert_eq!(finished[0].1.code(), Some(3));
+        assert_eq!(running.len(), 1);
+        assert_eq!(running[0].job.name, "slow");
+
+        running[0].child.kill().unwrap();
+        let finished = wait_for_jobs(&mut running).unwrap();
+        assert_eq!(finished[0].0.name, "slow");
+        assert!(running.is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use std::path::Path;
+
+use crate::Error;
+use crate::FileType;
+
+/// Entries scoring at or below this are not considered matches at all.
+pub(crate) const MIN_SCORE: usize = 1;
+
+/// Scores how well `entry` matches the search term `name`.
+///
+/// File names are compared case-insensitively, so `doom2.wad` always matches
+/// `DOOM2.WAD` even on case-sensitive filesystems; an exact-case match just
+/// scores higher. Among files with the same name, one in a directory meant for
+/// files of type `ty` (e.g. `iwads` for IWADs) scores higher.
+pub(crate) fn score_entry(name: &Path, entry: &Path, ty: FileType) -> Result<usize, Error> {
+    let base_name = name
+        .file_stem()
+        .ok_or_else(|| Error::NoFileStem(name.to_string_lossy().into_owned()))?;
+    let extension = name.extension();
+
+    let entry_extension = entry
+        .extension()
+        .map(|e| {
+            e.to_str()
+                .ok_or_else(|| Error::NonUtf8Path(entry.to_string_lossy().into_owned()))
+        })
+        .transpose()?
+        .unwrap_or("");
+
+    let mut score = 0;
+    let stem = entry
+        .file_stem()
+        .ok_or_else(|| Error::NoFileStem(entry.to_string_lossy().into_owned()))?;
+    let stems_eq = stem
+        .to_string_lossy()
+        .eq_ignore_ascii_case(base_name.to_string_lossy().as_ref());
+    let stems_case_eq = stem.to_string_lossy() == base_name.to_string_lossy();
+    let extensions_match = extension
+        .map(|ext| ext.to_string_lossy().eq_ignore_ascii_case(entry_extension))
+        .unwrap_or(true);
+    let ancestors_eq = in_query_dirs(name, entry);
+    if stems_eq {
+        // doom2
+        score += 2;
+    }
+    if stems_case_eq {
+        // DOOM2
+        score += 5;
+    }
+    if extensions_match {
+        // Example.wad
+        score += 1;
+        if stems_eq {
+            // doom2.wad
+            score += 10;
+        }
+        if stems_case_eq {
+            score += 5;
+        }
+    }
+    if stems_eq && ancestors_eq {
+        // iwad/doom2
+        score += 20;
+    }
+    if stems_eq && in_type_dir(entry, ty) {
+        // iwads/DOOM2.WAD
+        score += 1;
+    }
+    Ok(score)
+}
+
+/// Whether `entry` sits under the directories given in the search term `name`,
+/// e.g. `iwad/doom2` matches `/home/me/doom/iwad/DOOM2.WAD` but not
+/// `/home/me/doom/DOOM2.WAD`. A term without directories never matches.
+fn in_query_dirs(name: &Path, entry: &Path) -> bool {
+    let query_dirs = match name.parent() {
+        Some(parent) => parent.components().collect::<Vec<_>>(),
+        None => return false,
+    };
+    let entry_dirs = match entry.parent() {
+        Some(parent) => parent.components().collect::<Vec<_>>(),
+        None => return false,
+    };
+    !query_dirs.is_empty()
+        && entry_dirs.len() >= query_dirs.len()
+        && entry_dirs[entry_dirs.len() - query_dirs.len()..]
+            .iter()
+            .zip(&query_dirs)
+            .all(|(entry_dir, query_dir)| {
+                entry_dir
+                    .as_os_str()
+                    .to_string_lossy()
+                    .eq_ignore_ascii_case(&query_dir.as_os_str().to_string_lossy())
+            })
+}
+
+/// Whether `entry` is inside a directory conventionally holding files of type `ty`.
+fn in_type_dir(entry: &Path, ty: FileType) -> bool {
+    let dir_names: &[&str] = match ty {
+        FileType::Iwad => &["iwads"],
+        FileType::Demo => &["demos", "demo"],
+        FileType::Pwad => &[],
+    };
+    entry.ancestors().skip(1).any(|dir| {
+        dir.file_name()
+            .map(|name| {
+                dir_names
+                    .iter()
+                    .any(|dir_name| name.to_string_lossy().eq_ignore_ascii_case(dir_name))
+            })
+            .unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(name: &str, entry: &str) -> usize {
+        score_entry(Path::new(name), Path::new(entry), FileType::Iwad).unwrap()
+    }
+
+    #[test]
+    fn case_difference_matches() {
+        let different_case = score("doom2.wad", "/home/me/doom/DOOM2.WAD");
+        assert!(different_case > MIN_SCORE);
+        assert!(score("DOOM2.WAD", "/home/me/doom/doom2.wad") > MIN_SCORE);
+        assert!(score("doom2", "/home/me/doom/DOOM2.WAD") > MIN_SCORE);
+        assert!(different_case > score("doom2.wad", "/home/me/doom/doom2.deh"));
+        assert!(different_case > score("doom2.wad", "/home/me/doom/doom.wad"));
+    }
+
+    #[test]
+    fn exact_case_scores_higher() {
+        assert!(
+            score("DOOM2.WAD", "/home/me/doom/DOOM2.WAD")
+                > score("DOOM2.WAD", "/home/me/doom/doom2.wad")
+        );
+    }
+
+    #[test]
+    fn query_dirs_and_type_dirs_score_higher() {
+        assert!(
+            score("iwad/doom2.wad", "/home/me/doom/IWAD/DOOM2.WAD")
+                > score("iwad/doom2.wad", "/home/me/doom/DOOM2.WAD")
+        );
+        assert!(
+            score("doom2.wad", "/home/me/doom/iwads/DOOM2.WAD")
+                > score("doom2.wad", "/home/me/doom/pwads/DOOM2.WAD")
+        );
+    }
+
+    #[test]
+    fn unrelated_files_do_not_match() {
+        assert!(score("doom2.wad", "/home/me/doom/sunlust.wad") <= MIN_SCORE);
+    }
+
+    #[test]
+    fn demos_dir_scores_higher() {
+        let demo = |entry: &str| {
+            score_entry(Path::new("run.lmp"), Path::new(entry), FileType::Demo).unwrap()
+        };
+        assert!(demo("/home/me/doom/demos/run.lmp") > demo("/home/me/doom/wads/run.lmp"));
+        assert!(demo("/home/me/doom/demo/run.lmp") > demo("/home/me/doom/run.lmp"));
+        // The bonus is only for the directory of the type being searched for.
+        assert_eq!(
+            score("run.lmp", "/home/me/doom/demos/run.lmp"),
+            score("run.lmp", "/home/me/doom/wads/run.lmp")
+        );
+    }
+
+    #[test]
+    fn query_dirs_must_be_the_entry_parents() {
+        let entry = Path::new("/home/me/doom/iwad/DOOM2.WAD");
+        assert!(in_query_dirs(Path::new("iwad/doom2"), entry));
+        assert!(in_query_dirs(Path::new("doom/iwad/doom2"), entry));
+        assert!(in_query_dirs(Path::new("IWAD/doom2.wad"), entry));
+        assert!(!in_query_dirs(Path::new("doom2"), entry));
+        assert!(!in_query_dirs(Path::new("doom/doom2"), entry));
+        assert!(!in_query_dirs(Path::new("pwad/doom2"), entry));
+        assert!(!in_query_dirs(
+            Path::new("iwad/doom2"),
+            Path::new("/home/me/doom/iwad/old/DOOM2.WAD")
+        ));
+    }
+}
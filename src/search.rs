@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use log::trace;
+use log::warn;
+use once_cell::sync::Lazy;
+use walkdir::DirEntry;
+use walkdir::WalkDir;
+
+use crate::Error;
+
+/// The search index shared by every search in this invocation.
+pub(crate) static SEARCH_INDEX: Lazy<Mutex<SearchIndex>> =
+    Lazy::new(|| Mutex::new(SearchIndex::default()));
+
+/// How many directories deep searches look by default.
+pub(crate) const DEFAULT_SEARCH_DEPTH: usize = 8;
+
+/// Caches the files under each search directory so that resolving many names
+/// only walks each directory once.
+pub(crate) struct SearchIndex {
+    dirs: HashMap<PathBuf, Vec<PathBuf>>,
+    /// How many directories deep to look, from `--search-depth`.
+    depth: usize,
+    /// Whether to look inside hidden directories, from `--include-hidden`.
+    include_hidden: bool,
+    /// Directories never to look inside, from `--exclude-dir`.
+    excluded_dirs: Vec<PathBuf>,
+}
+
+impl SearchIndex {
+    pub fn new(depth: usize, include_hidden: bool, excluded_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            dirs: HashMap::new(),
+            depth,
+            include_hidden,
+            excluded_dirs,
+        }
+    }
+
+    /// Returns every file under `dir`, walking it on first use.
+    ///
+    /// Symbolic links are followed, but not into a directory that contains
+    /// them, and only `depth` directories deep. Hidden directories (such as
+    /// `.git`) are skipped unless `include_hidden` is set, and so is anything
+    /// in `excluded_dirs`.
+    pub fn files(&mut self, dir: &Path) -> Result<&[PathBuf], Error> {
+        if !self.dirs.contains_key(dir) {
+            trace!("Indexing '{}'", dir.to_string_lossy());
+            let mut files = vec![];
+            // Not contents first, or skipping a directory would skip the rest
+            // of its parent instead.
+            let walk = WalkDir::new(dir).follow_links(true).max_depth(self.depth);
+            let include_hidden = self.include_hidden;
+            let excluded_dirs = &self.excluded_dirs;
+            for entry in walk.into_iter().filter_entry(|entry| {
+                (include_hidden || !is_hidden_dir(entry))
+                    && !excluded_dirs
+                        .iter()
+                        .any(|dir| entry.path().starts_with(dir))
+            }) {
+                let entry = match entry {
+                    Err(e) if e.loop_ancestor().is_some() => {
+                        warn!("Not following symbolic link loop: {}", e);
+                        continue;
+                    }
+                    entry => entry?,
+                };
+                if !entry.path().is_dir() {
+                    files.push(entry.into_path());
+                }
+            }
+            self.dirs.insert(dir.to_path_buf(), files);
+        }
+        Ok(&self.dirs[dir])
+    }
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEARCH_DEPTH, false, vec![])
+    }
+}
+
+/// Whether `entry` is a hidden directory below the one being searched. The
+/// search directory itself may be hidden, like `~/.local/share/games/doom`.
+fn is_hidden_dir(entry: &DirEntry) -> bool {
+    entry.depth() > 0
+        && entry.file_type().is_dir()
+        && entry.file_name().to_string_lossy().starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop_terminates() {
+        let dir = tempfile::tempdir().unwrap();
+        let maps = dir.path().join("maps");
+        std::fs::create_dir(&maps).unwrap();
+        std::fs::write(maps.join("map01.wad"), "").unwrap();
+        std::os::unix::fs::symlink(dir.path(), maps.join("loop")).unwrap();
+
+        let mut index = SearchIndex::default();
+        let files = index.files(dir.path()).unwrap();
+        assert_eq!(files, [maps.join("map01.wad")]);
+    }
+
+    #[test]
+    fn skips_hidden_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join(".git/doom2.wad"), "").unwrap();
+        std::fs::write(dir.path().join(".doom2.wad"), "").unwrap();
+        std::fs::write(dir.path().join("doom2.wad"), "").unwrap();
+
+        let mut index = SearchIndex::default();
+        let mut files = index.files(dir.path()).unwrap().to_vec();
+        files.sort();
+        assert_eq!(
+            files,
+            [dir.path().join(".doom2.wad"), dir.path().join("doom2.wad")]
+        );
+    }
+
+    #[test]
+    fn skips_excluded_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let excluded = dir.path().join("backups");
+        std::fs::create_dir(&excluded).unwrap();
+        std::fs::write(excluded.join("doom2.wad"), "").unwrap();
+        std::fs::write(dir.path().join("doom2.wad"), "").unwrap();
+
+        let mut index = SearchIndex::new(DEFAULT_SEARCH_DEPTH, false, vec![excluded]);
+        let files = index.files(dir.path()).unwrap();
+        assert_eq!(files, [dir.path().join("doom2.wad")]);
+    }
+}
@@ -0,0 +1,295 @@
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::path::PathBuf;
+
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Confirm;
+use dialoguer::Input;
+use dialoguer::MultiSelect;
+use dialoguer::Select;
+use indexmap::IndexMap;
+use log::info;
+use log::warn;
+
+use crate::autoloads_path;
+use crate::engine::engines_path;
+use crate::engine::read_known_engines;
+use crate::engine::write_known_engines;
+use crate::engine::DoomEngine;
+use crate::engine::DoomEngineKind;
+use crate::engine::KnownEngines;
+use crate::iwad_dirs;
+use crate::util::absolute_path;
+use crate::util::write_error;
+use crate::Autoloads;
+use crate::Error;
+
+/// The binaries of well-known engines, without any extension, and their kinds.
+const KNOWN_ENGINES: [(&str, DoomEngineKind); 9] = [
+    ("dsda-doom", DoomEngineKind::MBF),
+    ("prboom-plus", DoomEngineKind::Boom),
+    ("woof", DoomEngineKind::MBF),
+    ("gzdoom", DoomEngineKind::ZDoom),
+    ("lzdoom", DoomEngineKind::ZDoom),
+    ("eternity", DoomEngineKind::Eternity),
+    ("crispy-doom", DoomEngineKind::Vanilla),
+    ("chocolate-doom", DoomEngineKind::Vanilla),
+    ("doomretro", DoomEngineKind::Vanilla),
+];
+
+/// Where engines are often installed, besides the directories in `$PATH`.
+#[cfg(unix)]
+const INSTALL_DIRS: &[&str] = &["/usr/games", "/usr/local/games", "/snap/bin"];
+#[cfg(windows)]
+const INSTALL_DIRS: &[&str] = &[];
+
+/// Walks through adding engines to the engines file, offering those installed
+/// or else asking for one, and writing an autoloads file listing the IWADs
+/// found, asking for anything that can't be worked out. With `no_confirm`,
+/// whatever can be worked out is accepted without asking.
+pub(crate) fn setup(no_confirm: bool) -> Result<(), Error> {
+    let theme = ColorfulTheme::default();
+
+    let mut added = vec![];
+    let installed = detect_installed_engines();
+    if installed.is_empty() {
+        info!("No installed engines were found.");
+    } else if no_confirm {
+        added = installed;
+    } else {
+        let chosen = MultiSelect::with_theme(&theme)
+            .with_prompt("These engines were found. Which should be added?")
+            .items(
+                &installed
+                    .iter()
+                    .map(|(name, binary, kind, description)| {
+                        format!(
+                            "{}: {} ({}, {})",
+                            name,
+                            description,
+                            binary.to_string_lossy(),
+                            kind.name()
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .defaults(&vec![true; installed.len()])
+            .interact()
+            .map_err(Error::Io)?;
+        added = installed
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| chosen.contains(i))
+            .map(|(_, engine)| engine)
+            .collect();
+    }
+    if added.is_empty() {
+        added.push(ask_for_engine(&theme, no_confirm)?);
+    }
+
+    let engines_path = engines_path()?;
+    let mut engines = if engines_path.exists() {
+        read_known_engines()?
+    } else {
+        KnownEngines::new(IndexMap::new())?
+    };
+    for (name, binary, kind, description) in &added {
+        let mut engine = DoomEngine::new(name, binary.clone(), *kind);
+        engine.description = description.clone();
+        engines.insert(name.clone(), engine)?;
+        info!("Adding engine '{}'.", name);
+    }
+    write_known_engines(&engines, &engines_path)?;
+    info!("Wrote '{}'.", engines_path.to_string_lossy());
+
+    let default_iwad_dir = iwad_dirs()?.into_iter().next().unwrap_or_default();
+    let iwad_dir = if no_confirm {
+        default_iwad_dir.clone()
+    } else {
+        let dir: String = Input::with_theme(&theme)
+            .with_prompt("Directory holding your IWADs")
+            .default(default_iwad_dir.to_string_lossy().into_owned())
+            .interact_text()
+            .map_err(Error::Io)?;
+        absolute_path(dir.trim())?
+    };
+    if !iwad_dir.exists() {
+        create_dir_all(&iwad_dir).map_err(write_error(&iwad_dir))?;
+    }
+    if !iwad_dirs()?.contains(&iwad_dir) {
+        info!(
+            "For IWADs to be found in '{}', pass it to --iwad-dir or set $DOOM_WADDIR to it.",
+            iwad_dir.to_string_lossy()
+        );
+    }
+
+    let autoloads_path = autoloads_path()?;
+    let overwrite = !autoloads_path.exists()
+        || (!no_confirm
+            && Confirm::with_theme(&theme)
+                .with_prompt(format!(
+                    "'{}' already exists. Replace it?",
+                    autoloads_path.to_string_lossy()
+                ))
+                .default(false)
+                .interact()
+                .map_err(Error::Io)?);
+    if overwrite {
+        let autoloads = Autoloads {
+            universal: vec![],
+            // Autoloads for an engine are keyed by its binary's name.
+            sourceport: added
+                .iter()
+                .filter_map(|(_, binary, _, _)| binary.file_stem())
+                .map(|stem| (stem.to_string_lossy().into_owned(), vec![]))
+                .collect(),
+            iwad: find_iwads(&iwad_dir)
+                .into_iter()
+                .map(|iwad| (iwad, vec![]))
+                .collect(),
+        };
+        let contents = toml::to_string(&autoloads).map_err(Error::TomlSerialize)?;
+        std::fs::write(&autoloads_path, contents).map_err(write_error(&autoloads_path))?;
+        info!(
+            "Wrote '{}'. Add the PWADs to load automatically to it.",
+            autoloads_path.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+/// Asks for the path to an engine's binary, then its name, kind and
+/// description.
+fn ask_for_engine(
+    theme: &ColorfulTheme,
+    no_confirm: bool,
+) -> Result<(String, PathBuf, DoomEngineKind, String), Error> {
+    let binary: String = Input::with_theme(theme)
+        .with_prompt("Path to a Doom engine's binary")
+        .interact_text()
+        .map_err(Error::Io)?;
+    let binary = absolute_path(binary.trim())?;
+    if !binary.exists() {
+        warn!("'{}' doesn't exist.", binary.to_string_lossy());
+    }
+    let stem = binary
+        .file_stem()
+        .ok_or_else(|| Error::NoFileStem(binary.to_string_lossy().into_owned()))?
+        .to_string_lossy()
+        .into_owned();
+
+    let guessed_kind = guess_kind(&stem);
+    let name = if no_confirm {
+        stem
+    } else {
+        Input::with_theme(theme)
+            .with_prompt("Name for the engine")
+            .default(stem)
+            .interact_text()
+            .map_err(Error::Io)?
+    };
+    let kind = match guessed_kind {
+        Some(kind) if no_confirm => kind,
+        _ => {
+            let default = guessed_kind
+                .and_then(|guessed| DoomEngineKind::ALL.iter().position(|&kind| kind == guessed))
+                .unwrap_or(0);
+            DoomEngineKind::ALL[Select::with_theme(theme)
+                .with_prompt("What kind of engine is it?")
+                .items(
+                    &DoomEngineKind::ALL
+                        .iter()
+                        .map(|kind| kind.name())
+                        .collect::<Vec<_>>(),
+                )
+                .default(default)
+                .interact()
+                .map_err(Error::Io)?]
+        }
+    };
+    let description = if no_confirm {
+        String::new()
+    } else {
+        Input::with_theme(theme)
+            .with_prompt("Short description (optional)")
+            .allow_empty(true)
+            .interact_text()
+            .map_err(Error::Io)?
+    };
+    Ok((name, binary, kind, description))
+}
+
+/// Looks for the binaries of well-known engines in `$PATH` and the usual places
+/// games are installed, returning the name, binary, kind and description of
+/// each found.
+fn detect_installed_engines() -> Vec<(String, PathBuf, DoomEngineKind, String)> {
+    let mut dirs = std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default();
+    dirs.extend(INSTALL_DIRS.iter().map(PathBuf::from));
+
+    let mut found = vec![];
+    for &(name, kind) in KNOWN_ENGINES.iter() {
+        let file_name = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+        if let Some(binary) = dirs
+            .iter()
+            .map(|dir| dir.join(&file_name))
+            .find(|binary| binary.is_file())
+        {
+            found.push((name.to_owned(), binary, kind, describe(name).to_owned()));
+        }
+    }
+    found
+}
+
+/// Describes one of the well-known engines.
+fn describe(name: &str) -> &'static str {
+    match name {
+        "dsda-doom" => "PrBoom+ fork for speedrunning",
+        "prboom-plus" => "Demo-compatible Boom port",
+        "woof" => "MBF port close to the original",
+        "gzdoom" => "Hardware-rendered ZDoom port",
+        "lzdoom" => "GZDoom for older hardware",
+        "eternity" => "Advanced MBF-derived port",
+        "crispy-doom" => "Chocolate Doom with extras",
+        "chocolate-doom" => "Faithful vanilla port",
+        "doomretro" => "Classic-feeling vanilla port",
+        _ => "",
+    }
+}
+
+/// Guesses what kind of engine a binary is from its name.
+fn guess_kind(binary_stem: &str) -> Option<DoomEngineKind> {
+    let stem = binary_stem.to_lowercase();
+    if stem.contains("zdoom") || stem.contains("zandronum") {
+        Some(DoomEngineKind::ZDoom)
+    } else if stem.contains("dsda") || stem.contains("woof") {
+        Some(DoomEngineKind::MBF)
+    } else if stem.contains("prboom") {
+        Some(DoomEngineKind::Boom)
+    } else if stem.contains("eternity") {
+        Some(DoomEngineKind::Eternity)
+    } else if stem.contains("chocolate") || stem.contains("crispy") {
+        Some(DoomEngineKind::Vanilla)
+    } else {
+        None
+    }
+}
+
+/// The names, without extensions, of the WADs directly in `dir`, which are
+/// presumably IWADs.
+fn find_iwads(dir: &Path) -> Vec<String> {
+    let mut iwads = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("wad"))
+        })
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .collect::<Vec<_>>();
+    iwads.sort();
+    iwads
+}
@@ -1,18 +1,444 @@
+use path_clean::PathClean;
+use std::io;
 use std::path::Path;
 use std::path::PathBuf;
-use path_clean::PathClean;
 
 use crate::doom_dir;
+use crate::home_dir;
 use crate::Error;
+use crate::ARG_SEPARATOR;
+
+/// Adds the file being read to an I/O error.
+pub(crate) fn read_error(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+    move |error| Error::ReadingFile {
+        path: path.to_path_buf(),
+        error,
+    }
+}
+
+/// Adds the file being written to an I/O error.
+pub(crate) fn write_error(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+    move |error| Error::WritingFile {
+        path: path.to_path_buf(),
+        error,
+    }
+}
+
+/// Adds the TOML file being parsed, whose contents are `contents`, to a TOML
+/// error, along with the line the error is on.
+pub(crate) fn toml_error<'a>(
+    path: &'a Path,
+    contents: &'a [u8],
+) -> impl FnOnce(toml::de::Error) -> Error + 'a {
+    move |error| Error::BadToml {
+        file: path.to_path_buf(),
+        snippet: error
+            .line_col()
+            .map(|(line, column)| error_snippet(&String::from_utf8_lossy(contents), line, column))
+            .unwrap_or_default(),
+        error,
+    }
+}
+
+/// Shows the line numbered `line` in `contents`, counting from 0, with a caret
+/// under the byte at `column`, as in
+///
+/// ```text
+///    7 | kind = Boom
+///      |        ^
+/// ```
+fn error_snippet(contents: &str, line: usize, column: usize) -> String {
+    let text = match contents.lines().nth(line) {
+        Some(text) => text,
+        None => return String::new(),
+    };
+    // Tabs are kept so that the caret lines up however wide they're shown.
+    let indent = text
+        .get(..column)
+        .unwrap_or(text)
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    format!("\n{:>4} | {}\n     | {}^", line + 1, text, indent)
+}
+
+/// Collects every item of `results`, or every error among them, so that
+/// several problems can be reported at once.
+pub(crate) fn collect_errors<T>(
+    results: impl IntoIterator<Item = Result<T, Error>>,
+) -> Result<Vec<T>, Error> {
+    let mut items = vec![];
+    let mut errors = vec![];
+    for result in results {
+        match result {
+            Ok(item) => items.push(item),
+            Err(Error::Multiple(more)) => errors.extend(more),
+            Err(error) => errors.push(error),
+        }
+    }
+    match errors.len() {
+        0 => Ok(items),
+        1 => Err(errors.remove(0)),
+        _ => Err(Error::Multiple(errors)),
+    }
+}
+
+/// Replaces each `${VAR}` in `value` with the environment variable `VAR`, or
+/// with nothing if it isn't set.
+pub(crate) fn expand_env(value: &str) -> String {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        expanded.push_str(&rest[..start]);
+        if let Some(var) = std::env::var_os(&rest[start + 2..end]) {
+            expanded.push_str(&var.to_string_lossy());
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// `path` if nothing exists there yet, or else the first of `stem-2.ext`,
+/// `stem-3.ext` and so on beside it that doesn't exist.
+pub(crate) fn next_free_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    (2..)
+        .map(|n| {
+            let mut file_name = format!("{}-{}", stem, n);
+            if let Some(extension) = path.extension() {
+                file_name.push('.');
+                file_name.push_str(&extension.to_string_lossy());
+            }
+            path.with_file_name(file_name)
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Finds the program called `name` in one of the directories in `$PATH`.
+pub(crate) fn find_in_path(name: &str) -> Option<PathBuf> {
+    let file_name = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+/// Expands a leading `~` to the user's home directory.
+fn expand_tilde(path: &Path) -> Result<PathBuf, Error> {
+    match path.strip_prefix("~") {
+        Ok(rest) if rest.as_os_str().is_empty() => home_dir(),
+        Ok(rest) => home_dir().map(|home| home.join(rest)),
+        Err(_) => Ok(path.to_path_buf()),
+    }
+}
 
 pub(crate) fn absolute_path(path: impl AsRef<Path>) -> Result<PathBuf, Error> {
-    let path = path.as_ref();
+    let path = expand_tilde(path.as_ref())?;
 
     let absolute_path = if path.is_absolute() {
-        path.to_path_buf()
+        path
     } else {
         doom_dir()?.join(path).clean()
     };
 
     Ok(absolute_path)
 }
+
+/// The Levenshtein distance between `a` and `b`, by characters.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Splits a list of files given as a single argument, on `,`, `:` or the
+/// platform's `ARG_SEPARATOR`. A colon that is part of a Windows drive letter
+/// (as in `C:\` or `C:/`) does not split.
+pub(crate) fn split_list(list: &str) -> Vec<&str> {
+    let is_separator = |c: char| c == ',' || c == ':' || c == ARG_SEPARATOR;
+    let bytes = list.as_bytes();
+    let mut items = vec![];
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        let is_drive_letter = c == ':'
+            && i == start + 1
+            && bytes[start].is_ascii_alphabetic()
+            && matches!(bytes.get(i + 1), Some(b'\\') | Some(b'/'));
+        if is_separator(c) && !is_drive_letter {
+            items.push(&list[start..i]);
+            start = i + 1;
+        }
+    }
+    items.push(&list[start..]);
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// Splits the contents of an arguments file into arguments, one or more per
+/// line. Arguments are separated by whitespace unless quoted with `'` or `"`;
+/// inside double quotes, and outside quotes, `\` escapes a quote, whitespace or
+/// another `\`. Blank lines and lines starting with `#` are skipped.
+pub(crate) fn split_args(file: &Path, contents: &str) -> Result<Vec<String>, Error> {
+    let mut args = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut arg: Option<String> = None;
+        let mut quote = None;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quote) {
+                ('\\', Some('"')) | ('\\', None)
+                    if chars.peek().is_some_and(|&next| is_escapable(next)) =>
+                {
+                    arg.get_or_insert_with(String::new)
+                        .push(chars.next().unwrap());
+                }
+                (c, Some(q)) if c == q => quote = None,
+                (c, Some(_)) => arg.get_or_insert_with(String::new).push(c),
+                ('"', None) | ('\'', None) => {
+                    quote = Some(c);
+                    arg.get_or_insert_with(String::new);
+                }
+                (c, None) if c.is_whitespace() => args.extend(arg.take()),
+                (c, None) => arg.get_or_insert_with(String::new).push(c),
+            }
+        }
+        if quote.is_some() {
+            return Err(Error::UnterminatedQuote {
+                file: file.to_path_buf(),
+                line: i + 1,
+            });
+        }
+        args.extend(arg);
+    }
+    Ok(args)
+}
+
+/// Whether `\` escapes `c` in an arguments file.
+fn is_escapable(c: char) -> bool {
+    c == '"' || c == '\'' || c == '\\' || c.is_whitespace()
+}
+
+/// Whether `name` matches the shell-style `pattern`, where `*` matches any run
+/// of characters and `?` any one character. Case is ignored, as WADs and demos
+/// are often named in capitals.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let name = name.to_lowercase().chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*` if the rest fails to match.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_free_path_counts_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("map01.mp4");
+        assert_eq!(next_free_path(&path), path);
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(next_free_path(&path), dir.path().join("map01-2.mp4"));
+        std::fs::write(dir.path().join("map01-2.mp4"), "").unwrap();
+        assert_eq!(next_free_path(&path), dir.path().join("map01-3.mp4"));
+    }
+
+    #[test]
+    fn next_free_path_without_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("demos");
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(next_free_path(&path), dir.path().join("demos-2"));
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("doom2", "doom2"), 0);
+        assert_eq!(edit_distance("", "doom"), 4);
+        assert_eq!(edit_distance("prboom", "prbom"), 1);
+        assert_eq!(edit_distance("gzdoom", "zdoom"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn splits_args_file() {
+        let contents = r#"
+            # Settings for recording.
+            -nomusic   -nosound
+            -config "/home/me/doom configs/record.cfg"
+
+            +set 'sv_cheats' 1 # not a comment
+            -file C:\wads\a\ b.wad "say \"hi\""
+        "#;
+        let args = split_args(Path::new("args.txt"), contents).unwrap();
+        assert_eq!(
+            args,
+            [
+                "-nomusic",
+                "-nosound",
+                "-config",
+                "/home/me/doom configs/record.cfg",
+                "+set",
+                "sv_cheats",
+                "1",
+                "#",
+                "not",
+                "a",
+                "comment",
+                "-file",
+                r"C:\wads\a b.wad",
+                r#"say "hi""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_quote_is_an_error() {
+        let contents = "-iwad doom2.wad\n-config \"record.cfg\n";
+        match split_args(Path::new("args.txt"), contents) {
+            Err(Error::UnterminatedQuote { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("expected an unterminated quote error"),
+        }
+    }
+
+    #[test]
+    fn expands_tilde() {
+        let home = home_dir().unwrap();
+        assert_eq!(expand_tilde(Path::new("~")).unwrap(), home);
+        assert_eq!(
+            expand_tilde(Path::new("~/wads/foo.wad")).unwrap(),
+            home.join("wads/foo.wad")
+        );
+    }
+
+    #[test]
+    fn leaves_other_paths_alone() {
+        assert_eq!(
+            expand_tilde(Path::new("~user/foo.wad")).unwrap(),
+            Path::new("~user/foo.wad")
+        );
+        assert_eq!(
+            expand_tilde(Path::new("/usr/share/doom/doom2.wad")).unwrap(),
+            Path::new("/usr/share/doom/doom2.wad")
+        );
+        assert_eq!(
+            expand_tilde(Path::new("wads/~")).unwrap(),
+            Path::new("wads/~")
+        );
+    }
+
+    #[test]
+    fn absolute_path_expands_tilde() {
+        assert_eq!(
+            absolute_path("~/wads/foo.wad").unwrap(),
+            home_dir().unwrap().join("wads/foo.wad")
+        );
+        assert_eq!(
+            absolute_path("/usr/share/doom/doom2.wad").unwrap(),
+            Path::new("/usr/share/doom/doom2.wad")
+        );
+    }
+
+    #[test]
+    fn splits_lists_on_commas_and_colons() {
+        assert_eq!(split_list("a.wad,b.wad"), ["a.wad", "b.wad"]);
+        assert_eq!(
+            split_list("a.wad:b.wad,,c.wad"),
+            ["a.wad", "b.wad", "c.wad"]
+        );
+        assert_eq!(
+            split_list(&format!("a.wad{}b.wad", ARG_SEPARATOR)),
+            ["a.wad", "b.wad"]
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn splits_lists_on_semicolons() {
+        assert_eq!(split_list(r"C:\wads;D:\more"), [r"C:\wads", r"D:\more"]);
+    }
+
+    #[test]
+    fn keeps_drive_letters_together() {
+        assert_eq!(split_list(r"C:\wads,D:\more"), [r"C:\wads", r"D:\more"]);
+        assert_eq!(
+            split_list(r"C:\wads\a.wad,D:/more/b.wad"),
+            [r"C:\wads\a.wad", "D:/more/b.wad"]
+        );
+        assert_eq!(split_list("e1m1:map01"), ["e1m1", "map01"]);
+    }
+
+    #[test]
+    fn splits_demo_lists_with_drive_letters() {
+        assert_eq!(
+            split_list(r"C:\a\run.lmp:D:\b\run2.lmp"),
+            [r"C:\a\run.lmp", r"D:\b\run2.lmp"]
+        );
+        assert_eq!(
+            split_list("/demos/run.lmp:run2.lmp"),
+            ["/demos/run.lmp", "run2.lmp"]
+        );
+    }
+
+    #[test]
+    fn args_file_keeps_drive_letters() {
+        let args = split_args(Path::new("args.txt"), r"-playdemo C:\a\run.lmp").unwrap();
+        assert_eq!(args, ["-playdemo", r"C:\a\run.lmp"]);
+    }
+
+    #[test]
+    fn expands_env_vars() {
+        std::env::set_var("PLAYDOOM_TEST_UTIL_DIR", "/srv/doom");
+        std::env::remove_var("PLAYDOOM_TEST_UTIL_UNSET");
+        assert_eq!(
+            expand_env("${PLAYDOOM_TEST_UTIL_DIR}/wads"),
+            "/srv/doom/wads"
+        );
+        assert_eq!(expand_env("a${PLAYDOOM_TEST_UTIL_UNSET}b"), "ab");
+        assert_eq!(expand_env("no vars"), "no vars");
+        assert_eq!(expand_env("unclosed ${VAR"), "unclosed ${VAR");
+    }
+}
@@ -0,0 +1,57 @@
+use crate::autoloads_path;
+use crate::engine::engines_path;
+use crate::engine::read_known_engines;
+use crate::read_autoloads;
+use crate::search_files;
+use crate::Error;
+use crate::FileType;
+
+/// Checks the engine and autoload definitions, returning every problem found
+/// rather than stopping at the first.
+pub(crate) fn validate() -> Result<Vec<Error>, Error> {
+    let mut problems = vec![];
+
+    let engines_path = engines_path()?;
+    if !engines_path.exists() {
+        problems.push(Error::FileNotFound(
+            engines_path.to_string_lossy().into_owned(),
+        ));
+    } else {
+        match read_known_engines() {
+            Ok(engines) => {
+                for (name, engine) in engines.iter_engines() {
+                    if !engine.binary.exists() {
+                        problems.push(Error::MissingBinary {
+                            engine: name.to_owned(),
+                            binary: engine.binary.clone(),
+                        });
+                    }
+                }
+            }
+            Err(Error::Multiple(errors)) => problems.extend(errors),
+            Err(e) => problems.push(e),
+        }
+    }
+
+    // A missing autoloads file is fine: it just means nothing is autoloaded.
+    let autoloads_path = autoloads_path()?;
+    if autoloads_path.exists() {
+        match read_autoloads(&autoloads_path) {
+            Ok(autoloads) => {
+                let pwads = autoloads
+                    .universal
+                    .iter()
+                    .chain(autoloads.sourceport.values().flatten())
+                    .chain(autoloads.iwad.values().flatten());
+                match search_files(&pwads.cloned().collect::<Vec<_>>(), FileType::Pwad) {
+                    Err(Error::Multiple(errors)) => problems.extend(errors),
+                    Err(e) => problems.push(e),
+                    Ok(_) => {}
+                }
+            }
+            Err(e) => problems.push(e),
+        }
+    }
+
+    Ok(problems)
+}